@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{path::PathBuf, process::Command, process::Stdio};
+use std::{collections::HashMap, collections::HashSet, collections::VecDeque, path::PathBuf, process::Command, process::Stdio, sync::Mutex, sync::OnceLock, sync::RwLock};
 use std::io::{BufRead, BufReader};
-use tauri::Emitter;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tauri::{Emitter, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::Semaphore;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MediaInfo {
     duration_seconds: f64,
     width: Option<u32>,
@@ -12,39 +16,495 @@ pub struct MediaInfo {
     fps: Option<f64>,
     bitrate_kbps: Option<u64>,
     has_video: bool,
+    has_audio: bool,
+    is_image: bool,
+    rotation: Option<u32>,
+    color_primaries: Option<String>,
+    color_transfer: Option<String>,
+    color_space: Option<String>,
+    // video minus audio length, only set when the gap is big enough that the shorter stream will
+    // visibly cut the output. mkv doesn't report per-stream durations, so it's always None there
+    av_duration_mismatch_secs: Option<f64>,
+    // the video stream already carries 360 projection side data
+    is_spherical: bool,
+    sample_rate: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversionResult {
+    output_path: String,
+    duration_seconds: f64,
+    dropped_frames: Option<u64>,
+    duplicated_frames: Option<u64>,
+    // max_duration_secs cut the output short
+    truncated: bool,
+    // the truncation plus anything the builder adjusted on its own, joined with "; "
+    warning: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CropRect {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DashOptions {
+    input_path: String,
+    output_dir: String,
+    renditions: Vec<DashRendition>,
+    audio_bitrate_kbps: Option<u64>,
+    segment_secs: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DashRendition {
+    width: u32,
+    height: u32,
+    // exactly one of these; small renditions can usually take a higher crf than the big ones
+    video_bitrate_kbps: Option<u64>,
+    crf: Option<u8>,
+    // optional cap for crf renditions, which also gives the manifest a bandwidth to advertise
+    max_bitrate_kbps: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashResult {
+    manifest_path: String,
+    segments: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LoudnessInfo {
+    integrated_lufs: Option<f64>,
+    loudness_range_lu: Option<f64>,
+    true_peak_dbfs: Option<f64>,
+    max_volume_db: Option<f64>,
+    mean_volume_db: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MuxOptions {
+    video_path: String,
+    audio_paths: Vec<MuxTrack>,
+    #[serde(default)]
+    subtitle_paths: Vec<MuxTrack>,
+    output_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MuxTrack {
+    path: String,
+    language: Option<String>,
+    // player auto-selection; at most one default per track type
+    #[serde(default)]
+    default: bool,
+    #[serde(default)]
+    forced: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VisualizerOptions {
+    input_audio: String,
+    output_path: String,
+    mode: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    background: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpriteSheetOptions {
+    input_path: String,
+    interval_secs: f64,
+    columns: u32,
+    thumb_width: u32,
+    output_image: String,
+    output_vtt: String,
 }
 
 #[derive(Debug, Deserialize)]
+pub struct ThumbnailOptions {
+    input_path: String,
+    count: u32,
+    width: Option<u32>,
+    // next to the source when unset
+    output_dir: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SpriteSheet {
+    output_image: String,
+    output_vtt: String,
+    thumbnails: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MediaTags {
+    format: HashMap<String, String>,
+    streams: Vec<StreamTags>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamTags {
+    index: u32,
+    codec_type: Option<String>,
+    tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConversionOptions {
     input_path: String,
     output_path: String,
     start_ms: u64,
     end_ms: u64,
+    // pasted timecodes ("1:23:45.500", "12:30", "90.5"); when set they win over start_ms/end_ms
+    start: Option<String>,
+    end: Option<String>,
+    // seek after -i: decodes from the start of the file but lands exactly on start_ms, even with copy
+    #[serde(default)]
+    precise_seek: bool,
+    // re-encoded trims fast-seek this far before start_ms and cut the rest on the output side;
+    // 0 goes back to a plain input seek
+    seek_preroll_ms: Option<u64>,
+    // cut flac/wav audio on exact samples instead of seeking; run_conversion fills in the rate
+    #[serde(default)]
+    sample_accurate: bool,
+    source_sample_rate: Option<u32>,
+    // "duration" (default) writes -t, "absolute" writes -to with end_ms on the source timeline
+    end_mode: Option<String>,
     width: Option<u32>,
     height: Option<u32>,
+    // resize relative to the source instead of width/height, e.g. 50.0 for half size
+    scale_percent: Option<f64>,
+    crop: Option<CropRect>,
+    // clockwise, 90/180/270, on top of the autorotation ffmpeg already does
+    rotate: Option<u32>,
+    // "scale_first" (default: width/height are the unrotated frame, 1920x1080 -> 1280x720 -> 720x1280)
+    // or "rotate_first" (width/height are the final, rotated frame)
+    transform_order: Option<String>,
+    // swscale algorithm for any resize, e.g. "lanczos" or "spline" for sharper downscales
+    scale_algorithm: Option<String>,
+    deinterlace: Option<String>,
     fps: Option<f64>,
+    // "film", "pal", "ntsc" or "hfr"; overrides fps with the exact fractional rate
+    fps_standard: Option<String>,
     video_bitrate_kbps: Option<u64>,
+    max_bitrate_kbps: Option<u64>,
+    buffer_size_kbps: Option<u64>,
     audio_bitrate_kbps: Option<u64>,
     format: Option<String>,
     is_audio_only: bool,
     video_codec: Option<String>,
+    // "cuda", "qsv", "videotoolbox", "vaapi", "d3d11va", "dxva2" or "auto"
+    hw_decode: Option<String>,
+    // skip the source's mp4 edit list; trimmed phone/editor exports otherwise start with black or
+    // frozen frames in players that ignore edit lists (older Android players, some smart TVs, Discord)
+    #[serde(default)]
+    ignore_edit_list: bool,
+    // shift timestamps to start at 0 so the mp4 muxer doesn't write an edit list for the cut;
+    // same players as above, plus QuickTime showing a frozen first frame
+    #[serde(default)]
+    avoid_negative_ts: bool,
+    // retried once with this video codec if the chosen one fails to start (hw encoder driver issues)
+    fallback_codec: Option<String>,
     audio_codec: Option<String>,
+    aac_profile: Option<String>,
+    aac_vbr: Option<u8>,
+    // "cbr", "abr" or "vbr"; unset keeps each encoder's own default (aac_vbr still implies vbr)
+    audio_rate_mode: Option<String>,
+    preset: Option<String>,
+    video_profile: Option<String>,
+    x264_params: Option<String>,
+    // keyframe every second and no B-frames, for scrubbing in editors; files come out ~20-40% bigger
+    #[serde(default)]
+    seek_friendly: bool,
+    // no lookahead or B-frames and a keyframe every second, for live-ish playback; the preset still
+    // sets the speed/quality trade-off, this wins for anything that adds latency
+    #[serde(default)]
+    low_latency: bool,
+    x265_params: Option<String>,
+    // carry HDR10 mastering display / content light levels over to libx265
+    #[serde(default)]
+    preserve_hdr_metadata: bool,
+    downmix: Option<String>,
+    sample_rate: Option<u32>,
+    channels: Option<u8>,
+    // encoder low-pass in Hz (aac and opus), keeps the highs an archival encode would otherwise lose
+    audio_cutoff_hz: Option<u32>,
+    spatial_audio: Option<String>,
+    // HRTF set for "binaural"; without one (or without sofalizer) it falls back to crossfeed
+    spatial_sofa_file: Option<String>,
+    speed: Option<f64>,
+    // minterpolate to `fps` instead of dropping/repeating frames; several times slower to encode
+    #[serde(default)]
+    interpolate: bool,
+    // plays the trimmed range backwards; reverse/areverse hold the whole range in memory
+    #[serde(default)]
+    reverse: bool,
+    audio_offset_ms: Option<i64>,
+    #[serde(default)]
+    timecode_overlay: bool,
+    timecode_position: Option<String>,
+    timecode_font_size: Option<u32>,
+    timecode_font_file: Option<String>,
+    gif_dither: Option<String>,
+    gif_max_colors: Option<u16>,
+    preview_duration_secs: Option<f64>,
+    // hard length cap for platforms like 60s reels; unlike a preview the result reports the cut
+    max_duration_secs: Option<f64>,
+    platform_preset: Option<String>,
+    // escape hatch: passed verbatim as -filter_complex, replacing the generated -vf/-af chains.
+    // The graph's outputs have to be picked with `maps` ("[v]", "[a]", "0:a", ...)
+    filter_complex: Option<String>,
+    maps: Option<Vec<String>>,
+    #[serde(default)]
+    copy_streams: bool,
+    #[serde(default)]
+    copy_video: bool,
+    // copy whatever the target container takes as is and re-encode only the rest; run_conversion
+    // turns this into copy_streams/copy_video from the source codecs
+    #[serde(default)]
+    smart_copy: bool,
+    #[serde(default)]
+    still_image: bool,
+    #[serde(default)]
+    write_report: bool,
+    // re-probe the output after a successful run and fail (deleting it) if it's empty or missing streams
+    #[serde(default)]
+    verify_output: bool,
+    // raw muxer flags without the dash, e.g. {"frag_duration": "2000000"}; see muxer_options_for_format
+    muxer_options: Option<HashMap<String, String>>,
+    // poster/album art embedded as an attached picture (mp4, mov, m4a, mp3, flac)
+    cover_image: Option<String>,
+    // video track to give an audio-only source: "none", "cover" (cover_image looped), "waveform" or "spectrum"
+    audio_video_mode: Option<String>,
+    // drop tags, chapters and the encoder string, e.g. before posting a phone clip publicly
+    #[serde(default)]
+    strip_metadata: bool,
+    // measure the exported range with ebur128 and tag its gain (replaygain_*, R128_TRACK_GAIN for
+    // opus) so players level it; the audio itself is untouched, so it pairs well with copy_streams
+    #[serde(default)]
+    tag_loudness: bool,
+    // filled in by run_conversion, written as -metadata key=value; kept in the resume state too
+    #[serde(default)]
+    extra_metadata: Vec<(String, String)>,
+    // carry mkv attachments (subtitle fonts) over to an mkv output; ignored for other formats
+    #[serde(default)]
+    preserve_attachments: bool,
+    // ffmetadata file (see export_chapters) whose chapters replace the source's; times are on the output timeline
+    import_chapters: Option<String>,
+    #[serde(default)]
+    inject_360_metadata: bool,
+    // tag the output as bt709; with convert_to_bt709 the pixels are also moved over via zscale
+    #[serde(default)]
+    force_bt709_tags: bool,
+    #[serde(default)]
+    convert_to_bt709: bool,
+    job_id: Option<String>,
+    // "low", "normal" or "high"; low keeps a background batch from starving the UI; high is windows only
+    priority: Option<String>,
+}
+
+#[tauri::command]
+async fn analyze_media(
+    window: tauri::Window,
+    path: String,
+    start_ms: Option<u64>,
+    probe_duration_ms: Option<u64>,
+    timeout_ms: Option<u64>,
+) -> Result<MediaInfo, String> {
+    ensure_safe_paths(&window, &[&path], &[])?;
+    // only read a window of packets when asked to; the header-level fields are still complete
+    let interval = match (start_ms, probe_duration_ms) {
+        (None, None) => None,
+        (start, duration) => {
+            let start = format_timestamp(start.unwrap_or(0));
+            Some(match duration {
+                Some(d) => format!("{start}%+{}", format_timestamp(d)),
+                None => format!("{start}%"),
+            })
+        }
+    };
+    // slow network drives can take seconds here, so let the UI show that something is happening
+    let _ = window.emit("analyze-started", path.clone());
+    // partial reads only see part of the packets, so only full probes go through the cache;
+    // a hit still sends both events so the UI doesn't have to special-case it
+    let cache_key = if interval.is_none() { probe_cache_key(&path) } else { None };
+    if let Some(info) = cache_key.as_ref().and_then(|key| window.state::<ProbeCache>().get(key)) {
+        let _ = window.emit("analyze-finished", path);
+        return Ok(info);
+    }
+    let timeout = timeout_ms.map(std::time::Duration::from_millis);
+    let result = probe_json_interval(path.clone(), interval, timeout)
+        .await
+        .and_then(parse_media_info);
+    if let (Some(key), Ok(info)) = (cache_key, &result) {
+        window.state::<ProbeCache>().insert(key, info.clone());
+    }
+    let _ = window.emit("analyze-finished", path);
+    result
+}
+
+/// analyze_media results for files that haven't changed since, most recently used at the back.
+/// A changed mtime or size is a different key, so stale entries just age out.
+#[derive(Default)]
+struct ProbeCache(Mutex<VecDeque<(ProbeKey, MediaInfo)>>);
+
+type ProbeKey = (String, std::time::SystemTime, u64);
+
+const PROBE_CACHE_SIZE: usize = 512;
+
+impl ProbeCache {
+    fn get(&self, key: &ProbeKey) -> Option<MediaInfo> {
+        let mut entries = self.0.lock().ok()?;
+        let index = entries.iter().position(|(k, _)| k == key)?;
+        let entry = entries.remove(index)?;
+        let info = entry.1.clone();
+        entries.push_back(entry);
+        Some(info)
+    }
+
+    fn insert(&self, key: ProbeKey, info: MediaInfo) {
+        let Ok(mut entries) = self.0.lock() else {
+            return;
+        };
+        // an older version of the same file is never going to be asked for again
+        entries.retain(|(k, _)| k.0 != key.0);
+        if entries.len() >= PROBE_CACHE_SIZE {
+            entries.pop_front();
+        }
+        entries.push_back((key, info));
+    }
+}
+
+fn probe_cache_key(path: &str) -> Option<ProbeKey> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((path.to_string(), meta.modified().ok()?, meta.len()))
+}
+
+#[tauri::command]
+fn clear_probe_cache(window: tauri::Window) {
+    if let Ok(mut entries) = window.state::<ProbeCache>().0.lock() {
+        entries.clear();
+    }
+}
+
+#[tauri::command]
+async fn read_tags(window: tauri::Window, path: String) -> Result<MediaTags, String> {
+    read_tags_within(safe_mode_root(&window), path).await
+}
+
+// the window only supplies the safe mode root, which keeps the check testable without one
+async fn read_tags_within(root: Option<PathBuf>, path: String) -> Result<MediaTags, String> {
+    ensure_paths_within(root.as_deref(), &[&path], &[])?;
+    let value = probe_json(path).await?;
+    parse_media_tags(&value)
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AnalyzeProgress {
+    path: String,
+    done: usize,
+    total: usize,
 }
 
 #[tauri::command]
-async fn analyze_media(path: String) -> Result<MediaInfo, String> {
+async fn analyze_directory(
+    window: tauri::Window,
+    dir: String,
+    extensions: Option<Vec<String>>,
+) -> Result<Vec<(String, Result<MediaInfo, String>)>, String> {
+    let extensions: Vec<String> = extensions
+        .unwrap_or_else(|| MEDIA_EXTENSIONS.iter().map(|e| e.to_string()).collect())
+        .into_iter()
+        .map(|e| e.trim_start_matches('.').to_ascii_lowercase())
+        .collect();
+    ensure_safe_paths(&window, &[&dir], &[])?;
+
+    let mut paths: Vec<String> = std::fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read folder: {e}"))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.extension()
+                .map(|e| extensions.contains(&e.to_string_lossy().to_ascii_lowercase()))
+                .unwrap_or(false)
+        })
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    paths.sort();
+
+    // every probe is its own ffprobe process, so cap how many run at once
+    let permits = Arc::new(Semaphore::new(MAX_PARALLEL_PROBES));
+    let done = Arc::new(AtomicUsize::new(0));
+    let total = paths.len();
+
+    let handles: Vec<_> = paths
+        .into_iter()
+        .map(|path| {
+            let permits = permits.clone();
+            let done = done.clone();
+            let window = window.clone();
+            tauri::async_runtime::spawn(async move {
+                let _permit = permits.acquire_owned().await.map_err(|e| e.to_string())?;
+                // a symlink in the folder can still point outside the root
+                let info = async {
+                    ensure_safe_paths(&window, &[&path], &[])?;
+                    parse_media_info(probe_json(path.clone()).await?)
+                }
+                .await;
+                let progress = AnalyzeProgress {
+                    path: path.clone(),
+                    done: done.fetch_add(1, Ordering::SeqCst) + 1,
+                    total,
+                };
+                let _ = window.emit("analyze-progress", progress);
+                Ok::<_, String>((path, info))
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(total);
+    for handle in handles {
+        results.push(handle.await.map_err(|e| format!("Probe task failed: {e}"))??);
+    }
+    Ok(results)
+}
+
+const MAX_PARALLEL_PROBES: usize = 4;
+// same list the file picker offers
+const MEDIA_EXTENSIONS: [&str; 11] = ["mp4", "mov", "mkv", "avi", "webm", "m4v", "mp3", "wav", "aac", "flac", "ogg"];
+
+async fn probe_json(path: String) -> Result<Value, String> {
+    probe_json_interval(path, None, None).await
+}
+
+async fn probe_json_interval(
+    path: String,
+    read_intervals: Option<String>,
+    timeout: Option<std::time::Duration>,
+) -> Result<Value, String> {
     let output = tauri::async_runtime::spawn_blocking(move || {
-        Command::new(resolve_tool("ffprobe"))
-            .args([
-                "-v",
-                "error",
-                "-print_format",
-                "json",
-                "-show_format",
-                "-show_streams",
-                &path,
-            ])
-            .output()
+        let mut cmd = Command::new(resolve_tool("ffprobe"));
+        cmd.args([
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+        ]);
+        if let Some(ref intervals) = read_intervals {
+            cmd.args(["-read_intervals", intervals]);
+        }
+        cmd.arg(&path);
+        match timeout {
+            Some(limit) => output_with_timeout(cmd, limit),
+            None => cmd.output(),
+        }
     })
     .await
     .map_err(|e| format!("Failed to join ffprobe task: {e}"))?
@@ -55,9 +515,7 @@ async fn analyze_media(path: String) -> Result<MediaInfo, String> {
         return Err(format!("ffprobe error: {stderr}"));
     }
 
-    let value: Value = serde_json::from_slice(&output.stdout)
-        .map_err(|e| format!("Failed to parse ffprobe JSON: {e}"))?;
-    parse_media_info(value)
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("Failed to parse ffprobe JSON: {e}"))
 }
 
 fn resolve_tool(tool: &str) -> PathBuf {
@@ -86,40 +544,134 @@ fn resolve_tool(tool: &str) -> PathBuf {
     PathBuf::from(tool)
 }
 
+// Like Command::output, but kills the child once `limit` passes. The pipes are drained on their
+// own threads so a chatty child can't block on a full pipe while we wait.
+fn output_with_timeout(mut cmd: Command, limit: std::time::Duration) -> std::io::Result<std::process::Output> {
+    use std::io::Read;
+
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+
+    let deadline = std::time::Instant::now() + limit;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if std::time::Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("gave up after {}ms", limit.as_millis()),
+            ));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    };
+
+    Ok(std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
 fn parse_media_info(value: Value) -> Result<MediaInfo, String> {
     let format = value
         .get("format")
         .ok_or_else(|| "Missing format section".to_string())?;
 
-    let duration_seconds = format
-        .get("duration")
-        .and_then(|d| d.as_str())
-        .and_then(|s| s.parse::<f64>().ok())
-        .ok_or_else(|| "Missing duration".to_string())?;
-
     let streams = value
         .get("streams")
         .and_then(|s| s.as_array())
         .ok_or_else(|| "Missing streams".to_string())?;
 
+    // cover art is a video stream too and is often listed first; the real video is the one that
+    // isn't an attached picture, and the biggest one if there are several
     let video_stream = streams
         .iter()
-        .find(|s| s.get("codec_type").and_then(|c| c.as_str()) == Some("video"));
+        .filter(|s| s.get("codec_type").and_then(|c| c.as_str()) == Some("video"))
+        // max_by_key keeps the last of equal streams, reversed that's the first one listed
+        .rev()
+        .max_by_key(|s| {
+            let attached_pic = s["disposition"]["attached_pic"].as_i64() == Some(1);
+            let pixels = s["width"].as_u64().unwrap_or(0) * s["height"].as_u64().unwrap_or(0);
+            (!attached_pic, pixels)
+        });
 
     let has_video = video_stream.is_some();
+    let has_audio = streams
+        .iter()
+        .any(|s| s.get("codec_type").and_then(|c| c.as_str()) == Some("audio"));
+    let is_image = !has_audio && video_stream.is_some_and(is_still_image_stream);
 
-    let (width, height, fps) = if let Some(vs) = video_stream {
-        let w = vs.get("width").and_then(|v| v.as_u64()).map(|v| v as u32);
-        let h = vs.get("height").and_then(|v| v.as_u64()).map(|v| v as u32);
-        let fps_val = vs
-            .get("avg_frame_rate")
+    // stills often carry no duration at all; treat them as zero-length instead of failing
+    let duration_seconds = format
+        .get("duration")
+        .and_then(|d| d.as_str())
+        .and_then(|s| s.parse::<f64>().ok())
+        .or(if is_image { Some(0.0) } else { None })
+        .ok_or_else(|| "Missing duration".to_string())?;
+
+    let rotation = video_stream.and_then(stream_rotation);
+    let stream_duration = |stream: &Value| {
+        stream
+            .get("duration")
+            .and_then(|d| d.as_str())
+            .and_then(|d| d.parse::<f64>().ok())
+    };
+    let audio_duration = streams
+        .iter()
+        .find(|s| s.get("codec_type").and_then(|c| c.as_str()) == Some("audio"))
+        .and_then(stream_duration);
+    // ffprobe reports sample_rate as a string
+    let sample_rate = streams
+        .iter()
+        .find(|s| s.get("codec_type").and_then(|c| c.as_str()) == Some("audio"))
+        .and_then(|s| s.get("sample_rate"))
+        .and_then(|r| r.as_str())
+        .and_then(|r| r.parse::<u32>().ok());
+    let av_duration_mismatch_secs = match (video_stream.and_then(stream_duration), audio_duration) {
+        (Some(v), Some(a)) if !is_image && (v - a).abs() > AV_MISMATCH_THRESHOLD_SECS => Some(v - a),
+        _ => None,
+    };
+    let color_tag = |key: &str| {
+        video_stream
+            .and_then(|vs| vs.get(key))
             .and_then(|v| v.as_str())
-            .and_then(parse_frame_rate)
-            .or_else(|| {
-                vs.get("r_frame_rate")
-                    .and_then(|v| v.as_str())
-                    .and_then(parse_frame_rate)
-            });
+            .filter(|v| *v != "unknown")
+            .map(|v| v.to_string())
+    };
+
+    let (width, height, fps) = if let Some(vs) = video_stream {
+        let mut w = vs.get("width").and_then(|v| v.as_u64()).map(|v| v as u32);
+        let mut h = vs.get("height").and_then(|v| v.as_u64()).map(|v| v as u32);
+        // report what players show, not the coded size, so a portrait phone clip reads as 1080x1920
+        if matches!(rotation, Some(90) | Some(270)) {
+            std::mem::swap(&mut w, &mut h);
+        }
+        let rate_field = |key: &str| {
+            vs.get(key)
+                .and_then(|v| v.as_str())
+                .and_then(parse_frame_rate)
+                // "0/1" parses fine but is just as useless as "0/0"
+                .filter(|f| f.is_finite() && *f > 0.0)
+        };
+        // some containers leave both rate fields degenerate, counting frames still works there
+        let fps_val = rate_field("avg_frame_rate").or_else(|| rate_field("r_frame_rate")).or_else(|| {
+            let frames = vs.get("nb_frames").and_then(|n| n.as_str()).and_then(|n| n.parse::<f64>().ok())?;
+            let secs = stream_duration(vs).unwrap_or(duration_seconds);
+            (frames > 0.0 && secs > 0.0).then(|| frames / secs)
+        });
         (w, h, fps_val)
     } else {
         (None, None, None)
@@ -145,9 +697,109 @@ fn parse_media_info(value: Value) -> Result<MediaInfo, String> {
         fps,
         bitrate_kbps,
         has_video,
+        has_audio,
+        is_image,
+        rotation,
+        color_primaries: color_tag("color_primaries"),
+        color_transfer: color_tag("color_transfer"),
+        color_space: color_tag("color_space"),
+        av_duration_mismatch_secs,
+        is_spherical: video_stream.is_some_and(has_spherical_side_data),
+        sample_rate,
+    })
+}
+
+// Muxers pad streams by a frame or two as a matter of course, so small gaps aren't worth flagging.
+const AV_MISMATCH_THRESHOLD_SECS: f64 = 0.5;
+
+const IMAGE_CODECS: [&str; 6] = ["png", "mjpeg", "bmp", "webp", "tiff", "jpegls"];
+
+fn is_still_image_stream(stream: &Value) -> bool {
+    let codec = stream.get("codec_name").and_then(|c| c.as_str()).unwrap_or("");
+    // mjpeg is also used for real video, so it only counts as a still when there's a single frame
+    let frames = stream
+        .get("nb_frames")
+        .and_then(|n| n.as_str())
+        .and_then(|n| n.parse::<u64>().ok());
+    IMAGE_CODECS.contains(&codec) && frames.is_none_or(|n| n <= 1)
+}
+
+fn has_spherical_side_data(stream: &Value) -> bool {
+    stream
+        .get("side_data_list")
+        .and_then(|l| l.as_array())
+        .is_some_and(|list| {
+            list.iter()
+                .any(|sd| sd.get("side_data_type").and_then(|t| t.as_str()) == Some("Spherical Mapping"))
+        })
+}
+
+/// Clockwise display rotation in degrees (0, 90, 180 or 270), from the display matrix side data
+/// newer ffprobe builds report, or the legacy `rotate` tag.
+fn stream_rotation(stream: &Value) -> Option<u32> {
+    let from_matrix = stream
+        .get("side_data_list")
+        .and_then(|l| l.as_array())
+        .and_then(|list| list.iter().find_map(|sd| sd.get("rotation").and_then(|r| r.as_f64())))
+        // the display matrix angle is counter-clockwise
+        .map(|r| -r);
+    let degrees = from_matrix.or_else(|| {
+        stream
+            .get("tags")
+            .and_then(|t| t.get("rotate"))
+            .and_then(|r| r.as_str())
+            .and_then(|r| r.parse::<f64>().ok())
+    })?;
+    let normalized = (degrees.round() as i64).rem_euclid(360) as u32;
+    Some(normalized)
+}
+
+fn parse_media_tags(value: &Value) -> Result<MediaTags, String> {
+    let format = value
+        .get("format")
+        .ok_or_else(|| "Missing format section".to_string())?;
+
+    let streams = value
+        .get("streams")
+        .and_then(|s| s.as_array())
+        .ok_or_else(|| "Missing streams".to_string())?
+        .iter()
+        .enumerate()
+        .map(|(i, s)| StreamTags {
+            index: s
+                .get("index")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as u32)
+                .unwrap_or(i as u32),
+            codec_type: s
+                .get("codec_type")
+                .and_then(|c| c.as_str())
+                .map(|c| c.to_string()),
+            tags: tags_to_map(s.get("tags")),
+        })
+        .collect();
+
+    Ok(MediaTags {
+        format: tags_to_map(format.get("tags")),
+        streams,
     })
 }
 
+fn tags_to_map(tags: Option<&Value>) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    if let Some(obj) = tags.and_then(|t| t.as_object()) {
+        for (key, val) in obj {
+            // ffprobe emits tag values as strings, but be lenient with odd muxers
+            let text = match val {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            map.insert(key.clone(), text);
+        }
+    }
+    map
+}
+
 fn parse_frame_rate(rate: &str) -> Option<f64> {
     if let Some((num, den)) = rate.split_once('/') {
         let num: f64 = num.parse().ok()?;
@@ -163,232 +815,5044 @@ fn parse_frame_rate(rate: &str) -> Option<f64> {
 }
 
 #[tauri::command]
-async fn run_conversion(window: tauri::Window, options: ConversionOptions) -> Result<(), String> {
-    tauri::async_runtime::spawn_blocking(move || {
-        let args = build_ffmpeg_args(&options).map_err(|e| format!("Argument error: {}", e))?;
-        let mut child = Command::new(resolve_tool("ffmpeg"))
-            .args(&args)
-            .stdout(Stdio::piped()) // just in case i need it
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| format!("Failed to spawn: {}", e))?;
-
-        let stderr = child.stderr.take().ok_or("Failed to open stderr")?;
-        let reader = BufReader::new(stderr);
-
-        // line by line stream of output
-        for line in reader.lines() {
-            match line {
-                Ok(text) => {
-                    window.emit("PROGRESS", text.clone()).unwrap();
-                    println!("{}", text);
-                }
-                Err(e) => {
-                    println!("Error: {}", e);
-                }
+async fn run_conversion(window: tauri::Window, options: ConversionOptions) -> Result<ConversionResult, ConversionError> {
+    let write_report = options.write_report;
+    let verify = options.verify_output;
+    let is_audio_only = options.is_audio_only;
+    let input_path = options.input_path.clone();
+    let (mut options, write_spherical_box) = prepare_options(&window, options).await?;
+    let _slot = acquire_conversion_slot(&window).await?;
+
+    let (result, args, encode_seconds) = tauri::async_runtime::spawn_blocking(move || {
+        let mut notes = Vec::new();
+        let mut args = build_ffmpeg_args_noted(&options, &mut notes)
+            .map_err(|e| ConversionError::new(ErrorCode::InvalidOptions, format!("Argument error: {}", e)))?;
+        let started = std::time::Instant::now();
+        let mut outcome = run_ffmpeg_at(&window, &args, options.priority.as_deref());
+        // a single retry: the fallback itself failing is reported as is
+        let fallback = options.fallback_codec.take().filter(|f| options.video_codec.as_ref() != Some(f));
+        if let (Err(e), Some(fallback)) = (&outcome, fallback) {
+            if e.code == ErrorCode::UnsupportedCodec && !options.is_audio_only {
+                let from = options.video_codec.replace(fallback.clone()).unwrap_or_default();
+                let _ = window.emit("codec-fallback", CodecFallback { from, to: fallback });
+                notes.clear();
+                args = build_ffmpeg_args_noted(&options, &mut notes)
+                    .map_err(|e| ConversionError::new(ErrorCode::InvalidOptions, format!("Argument error: {}", e)))?;
+                outcome = run_ffmpeg_at(&window, &args, options.priority.as_deref());
             }
         }
+        let encode_seconds = started.elapsed().as_secs_f64();
+        if let Some(ref job_id) = options.job_id {
+            // remember interrupted jobs so resume_conversion can pick them up later
+            let state = if outcome.is_err() { Some(&options) } else { None };
+            save_resume_state(&window, job_id, state)?;
+        }
+        let stats = outcome?;
+        if write_spherical_box {
+            inject_spherical_metadata(std::path::Path::new(&options.output_path))?;
+        }
 
-        let status = child.wait().map_err(|e| format!("Waiting failed: {}", e))?;
+        let result = conversion_result(&options, &stats, &notes);
+        Ok::<_, ConversionError>((result, args, encode_seconds))
+    }).await.map_err(|e| format!("Task failed: {}", e))??;
 
-        if status.success() {
-            Ok(()) // last line, dont add semicolon
-        } else {
-            Err("ffmpeg failed".to_string())
+    if verify {
+        if let Err(e) = verify_conversion_output(&input_path, &result.output_path, &args, is_audio_only).await {
+            let _ = std::fs::remove_file(&result.output_path);
+            return Err(ConversionError::new(ErrorCode::Unknown, format!("Output failed verification: {e}")));
         }
-    }).await.map_err(|e| format!("Task failed: {}", e))??;
+    }
 
-    Ok(())
+    if write_report {
+        write_conversion_report(&input_path, &result.output_path, &args, encode_seconds).await?;
+    }
+
+    Ok(result)
 }
 
-fn video_codecs_for_format(fmt: &str) -> Vec<&'static str> {
-    match fmt {
-        "mp4" => vec!["libx264", "libx265"],
-        "mov" => vec!["libx264", "libx265", "prores_ks", "mjpeg"],
-        "mkv" => vec!["libx264", "libx265", "libvpx-vp9", "prores_ks", "mjpeg"],
-        "webm" => vec!["libvpx-vp9"],
-        "avi" => vec!["libx264", "mjpeg"],
-        "flv" => vec!["libx264"],
-        "gif" => vec!["gif"],
-        _ => vec![],
+/// Everything a conversion does before its args are built: safe mode, the default preset,
+/// validation against the source and the probe-driven fills (smart copy, HDR, scale, loudness
+/// tags, sample rate, bt709). The flag says whether the 360 box still has to be written after
+/// ffmpeg is done. The batch commands run each of their outputs through this too.
+async fn prepare_options(
+    window: &tauri::Window,
+    options: ConversionOptions,
+) -> Result<(ConversionOptions, bool), ConversionError> {
+    let mut options = options;
+    resolve_timestamp_strings(&mut options).map_err(|e| ConversionError::new(ErrorCode::InvalidOptions, e))?;
+    ensure_safe_options(window, &options).map_err(|e| ConversionError::new(ErrorCode::PermissionDenied, e))?;
+    apply_default_preset(window, &mut options);
+    let problems = collect_validation_errors(&options);
+    if !problems.is_empty() {
+        let code = if problems.iter().any(|p| p.field == "input_path") { ErrorCode::FileNotFound } else { ErrorCode::InvalidOptions };
+        let messages: Vec<String> = problems.into_iter().map(|p| p.message).collect();
+        return Err(ConversionError::new(code, messages.join("; ")));
+    }
+    let source_json = probe_json(options.input_path.clone()).await?;
+    let source = parse_media_info(source_json.clone())?;
+    ensure_trim_within_source(&options, &source).map_err(|e| ConversionError::new(ErrorCode::InvalidOptions, e))?;
+    if options.smart_copy {
+        apply_smart_copy(&mut options, &source_json).map_err(|e| ConversionError::new(ErrorCode::InvalidOptions, e))?;
     }
+    // the mastering display and light levels are frame side data, which the stream probe above doesn't read
+    if options.preserve_hdr_metadata {
+        let hdr = probe_hdr_params(options.input_path.clone()).await?;
+        options.x265_params = Some(match options.x265_params.take() {
+            Some(user) => format!("{user}:{hdr}"),
+            None => hdr,
+        });
+    }
+    if let Some(percent) = options.scale_percent {
+        apply_scale_percent(&mut options, percent, &source).map_err(|e| ConversionError::new(ErrorCode::InvalidOptions, e))?;
+    }
+    if options.tag_loudness {
+        let format = options.format.clone().unwrap_or_else(|| "mp4".to_string());
+        if options.strip_metadata || !matches!(format.as_str(), "mp3" | "flac" | "ogg" | "opus" | "mkv") {
+            return Err(ConversionError::new(
+                ErrorCode::InvalidOptions,
+                format!("Loudness tags can't be written to {format} or together with strip_metadata"),
+            ));
+        }
+        let loudness = measure_loudness(options.input_path.clone(), Some((options.start_ms, options.end_ms))).await?;
+        options.extra_metadata.extend(loudness_tags(&loudness, &format, options.audio_codec.as_deref())?);
+    }
+    if (options.sample_accurate || options.audio_cutoff_hz.is_some()) && options.source_sample_rate.is_none() {
+        options.source_sample_rate = source.sample_rate;
+    }
+    if options.convert_to_bt709 {
+        options.convert_to_bt709 = needs_bt709_conversion(&source)?;
+    }
+    // tagged sources get their projection carried over by the muxer, only untagged ones need the box written
+    let write_spherical_box = options.inject_360_metadata && !source.is_spherical;
+    let sofalizer_missing = window
+        .state::<FfmpegCapabilities>()
+        .filters()
+        .is_some_and(|f| !f.contains("sofalizer"));
+    if options.spatial_audio.as_deref() == Some("binaural") && sofalizer_missing {
+        options.spatial_sofa_file = None;
+    }
+    Ok((options, write_spherical_box))
 }
 
-fn audio_codecs_for_format(fmt: &str) -> Vec<&'static str> {
-    match fmt {
-        "mp4" => vec!["aac", "libmp3lame"],
-        "mov" => vec!["aac"],
-        "mkv" => vec!["aac", "libopus", "libvorbis", "libmp3lame", "flac"],
-        "webm" => vec!["libopus", "libvorbis"],
-        "avi" => vec!["libmp3lame"],
-        "flv" => vec!["aac"],
-        "gif" => vec![],
-        "mp3" => vec!["libmp3lame"],
-        "wav" => vec!["pcm_s16le"],
-        "flac" => vec!["flac"],
-        "m4a" | "aac" => vec!["aac"],
-        "ogg" => vec!["libvorbis"],
-        "opus" => vec!["libopus"],
-        _ => vec![],
-    }
+#[derive(Debug, Serialize)]
+pub struct MasterResult {
+    conversion: ConversionResult,
+    master_path: String,
 }
 
-fn build_ffmpeg_args(options: &ConversionOptions) -> Result<Vec<String>, String> {
-    let mut args: Vec<String> = Vec::new();
-    args.push("-y".to_string());
+/// The requested (usually lossy) conversion plus a lossless FLAC/WAV master of the same range,
+/// written by one ffmpeg run so the source is only decoded once. The master carries the source
+/// audio as is, without the speed/offset/downmix changes applied to the main output.
+#[tauri::command]
+async fn convert_with_master(
+    window: tauri::Window,
+    options: ConversionOptions,
+    master_format: Option<String>,
+    master_path: Option<String>,
+) -> Result<MasterResult, String> {
+    let (options, write_spherical_box) = prepare_options(&window, options).await?;
+    let master_format = master_format.unwrap_or_else(|| "flac".to_string());
+    let master_codec = match master_format.as_str() {
+        "flac" => "flac",
+        "wav" => "pcm_s16le",
+        other => return Err(format!("Masters can be flac or wav, not {other}")),
+    };
+    if options.reverse || options.filter_complex.is_some() {
+        return Err("A master can't be split off a reversed or custom-graph conversion".to_string());
+    }
+    let master_path = master_path.unwrap_or_else(|| {
+        let output = PathBuf::from(&options.output_path);
+        let stem = output
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "output".to_string());
+        avoid_collision(output.with_file_name(format!("{stem}_master.{master_format}")))
+            .to_string_lossy()
+            .to_string()
+    });
 
-    let format = options.format.as_deref().unwrap_or("mp4");
-    let start_secs = options.start_ms as f64 / 1000.0;
-    let duration_secs = (options.end_ms - options.start_ms) as f64 / 1000.0;
+    if let Some(root) = safe_mode_root(&window) {
+        ensure_within_root(&master_path, &root, false)?;
+    }
 
-    if start_secs > 0.0 {
-        args.push("-ss".to_string());
-        args.push(format!("{start_secs:.3}"));
+    let _slot = acquire_conversion_slot(&window).await?;
+    let master = master_path.clone();
+    let conversion = tauri::async_runtime::spawn_blocking(move || {
+        let mut notes = Vec::new();
+        let mut args = build_ffmpeg_args_noted(&options, &mut notes).map_err(|e| format!("Argument error: {}", e))?;
+        // the main output's seek depends on the preset's container and the visualizer graph
+        let applied = with_presets_applied(&options)?;
+        // second output: everything after the first output path only applies to the master
+        let mut trimmed_ms = applied.end_ms.saturating_sub(applied.start_ms);
+        if let Some(cap) = output_length_cap(&applied) {
+            trimmed_ms = trimmed_ms.min((cap * 1000.0) as u64);
+        }
+        args.extend(["-map", "0:a:0"].map(String::from));
+        if applied.precise_seek && applied.start_ms > 0 {
+            args.push("-ss".to_string());
+            args.push(format_timestamp(applied.start_ms));
+        } else if let Some(preroll) = hybrid_preroll_ms(&applied, applied.format.as_deref().unwrap_or("mp4")) {
+            args.push("-ss".to_string());
+            args.push(format_timestamp(preroll));
+        }
+        args.push("-t".to_string());
+        args.push(format_timestamp(trimmed_ms));
+        args.push("-c:a".to_string());
+        args.push(master_codec.to_string());
+        args.push(master);
+
+        let stats = run_ffmpeg_at(&window, &args, options.priority.as_deref())?;
+        if write_spherical_box {
+            inject_spherical_metadata(std::path::Path::new(&options.output_path))?;
+        }
+        Ok::<_, String>(conversion_result(&options, &stats, &notes))
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))??;
+
+    Ok(MasterResult { conversion, master_path })
+}
+
+/// One output per format from the same options, e.g. an mp4 and a webm of a clip. When every
+/// format reads the inputs the same way they share one ffmpeg run (one decode, several outputs);
+/// otherwise, or with a custom graph whose labels can only be mapped once, they run one by one.
+/// Output paths are output_path with the extension swapped for the format.
+#[tauri::command]
+async fn convert_multi_format(
+    window: tauri::Window,
+    options: ConversionOptions,
+    formats: Vec<String>,
+) -> Result<Vec<(String, Result<ConversionResult, String>)>, String> {
+    if formats.is_empty() {
+        return Err("Pick at least one output format".to_string());
+    }
+    // the same format twice would just encode the same file again under a _1 name
+    let mut seen = HashSet::new();
+    if let Some(dup) = formats.iter().find(|f| !seen.insert(f.as_str())) {
+        return Err(format!("{dup} is listed more than once"));
     }
 
-    args.push("-i".to_string());
-    args.push(options.input_path.clone());
+    let base = PathBuf::from(&options.output_path);
+    let mut per_format: Vec<ConversionOptions> = Vec::with_capacity(formats.len());
+    let mut spherical_boxes: Vec<bool> = Vec::with_capacity(formats.len());
+    for format in &formats {
+        let format_options = ConversionOptions {
+            format: Some(format.clone()),
+            output_path: avoid_collision(base.with_extension(format)).to_string_lossy().to_string(),
+            job_id: None,
+            ..options.clone()
+        };
+        // codecs, smart copy and loudness tags all depend on the container, so each is prepared on its own
+        let (prepared, write_spherical_box) = prepare_options(&window, format_options).await?;
+        per_format.push(prepared);
+        spherical_boxes.push(write_spherical_box);
+    }
+    let mut notes: Vec<Vec<String>> = vec![Vec::new(); per_format.len()];
+    let arg_lists = per_format
+        .iter()
+        .zip(notes.iter_mut())
+        .map(|(options, notes)| build_ffmpeg_args_noted(options, notes))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Argument error: {}", e))?;
 
-    args.push("-t".to_string());
-    args.push(format!("{duration_secs:.3}"));
+    // everything up to the last input is shared, only what follows is per output
+    let input_end = |args: &[String]| args.iter().rposition(|a| a == "-i").map(|i| i + 2).unwrap_or(1);
+    let first_inputs = &arg_lists[0][..input_end(&arg_lists[0])];
+    let shareable = arg_lists.iter().all(|args| {
+        let end = input_end(args);
+        &args[..end] == first_inputs && !args[end..].iter().any(|a| a == "-filter_complex")
+    });
 
-    if options.is_audio_only {
-        let allowed_audio = audio_codecs_for_format(format);
-        if allowed_audio.is_empty() {
-            return Err(format!("No audio codecs available for format: {format}"));
-        }
-        let audio_codec = if let Some(ref user) = options.audio_codec {
-            if allowed_audio.iter().any(|c| c == user) {
-                user.as_str()
-            } else {
-                return Err(format!("Audio codec {user} not allowed for format {format}"));
+    let _slot = acquire_conversion_slot(&window).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let result_for = |options: &ConversionOptions, write_spherical_box: bool, stats: &FfmpegStats, notes: &[String]| {
+            if write_spherical_box {
+                inject_spherical_metadata(std::path::Path::new(&options.output_path))?;
             }
-        } else {
-            allowed_audio[0]
+            Ok(conversion_result(options, stats, notes))
         };
-        args.push("-vn".to_string());
-        args.push("-c:a".to_string());
-        args.push(audio_codec.to_string());
-        if let Some(ab) = options.audio_bitrate_kbps {
-            args.push("-b:a".to_string());
-            args.push(format!("{ab}k"));
+        if shareable {
+            let mut args = arg_lists[0].clone();
+            for other in &arg_lists[1..] {
+                args.extend_from_slice(&other[input_end(other)..]);
+            }
+            let outcome = run_ffmpeg_at(&window, &args, options.priority.as_deref()).map_err(String::from);
+            return formats
+                .into_iter()
+                .zip(per_format.iter().zip(spherical_boxes))
+                .zip(&notes)
+                .map(|((format, (options, write_spherical_box)), notes)| {
+                    let result = match &outcome {
+                        Ok(stats) => result_for(options, write_spherical_box, stats, notes),
+                        Err(e) => Err(e.clone()),
+                    };
+                    (format, result)
+                })
+                .collect();
+        }
+        formats
+            .into_iter()
+            .zip(per_format.iter().zip(spherical_boxes))
+            .zip(arg_lists.iter().zip(&notes))
+            .map(|((format, (options, write_spherical_box)), (args, notes))| {
+                let outcome = run_ffmpeg_at(&window, args, options.priority.as_deref()).map_err(String::from);
+                (format, outcome.and_then(|stats| result_for(options, write_spherical_box, &stats, notes)))
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CodecFallback {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrimRange {
+    start_ms: u64,
+    end_ms: u64,
+    output_path: String,
+}
+
+/// Cuts every range into its own file with the same settings, one after another. A failing range
+/// doesn't stop the rest, each one reports its own result.
+#[tauri::command]
+async fn trim_multiple(
+    window: tauri::Window,
+    input_path: String,
+    ranges: Vec<TrimRange>,
+    shared_options: ConversionOptions,
+) -> Result<Vec<(String, Result<ConversionResult, String>)>, String> {
+    if ranges.is_empty() {
+        return Err("Add at least one range to cut".to_string());
+    }
+    let mut results = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        let options = ConversionOptions {
+            input_path: input_path.clone(),
+            output_path: range.output_path.clone(),
+            start_ms: range.start_ms,
+            end_ms: range.end_ms,
+            // per-range values only, the shared ones would override every range
+            start: None,
+            end: None,
+            job_id: None,
+            ..shared_options.clone()
+        };
+        // each range has its own trim to check and loudness to measure
+        let (options, write_spherical_box) = match prepare_options(&window, options).await {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                results.push((range.output_path, Err(e.into())));
+                continue;
+            }
+        };
+        let _slot = acquire_conversion_slot(&window).await?;
+        let window = window.clone();
+        let outcome = tauri::async_runtime::spawn_blocking(move || {
+            let mut notes = Vec::new();
+            let args = build_ffmpeg_args_noted(&options, &mut notes).map_err(|e| format!("Argument error: {}", e))?;
+            let stats = run_ffmpeg_at(&window, &args, options.priority.as_deref())?;
+            if write_spherical_box {
+                inject_spherical_metadata(std::path::Path::new(&options.output_path))?;
+            }
+            Ok::<_, String>(conversion_result(&options, &stats, &notes))
+        })
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?;
+        results.push((range.output_path, outcome));
+    }
+    Ok(results)
+}
+
+#[derive(Debug, Serialize)]
+struct ConversionReport {
+    command: Vec<String>,
+    input: Option<MediaInfo>,
+    output: Option<MediaInfo>,
+    encode_seconds: f64,
+    finished_at_unix: u64,
+}
+
+// ffmpeg can exit 0 and still leave an unplayable file behind (empty mdat, dropped streams), so
+// probe the result the same way analyze_media would.
+async fn verify_conversion_output(input: &str, output: &str, args: &[String], is_audio_only: bool) -> Result<(), String> {
+    let size = std::fs::metadata(output).map_err(|e| format!("output is missing: {e}"))?.len();
+    if size == 0 {
+        return Err("output is empty".to_string());
+    }
+    let info = parse_media_info(probe_json(output.to_string()).await?)?;
+    if info.duration_seconds <= 0.0 {
+        return Err("output has no duration".to_string());
+    }
+    if !is_audio_only && !info.has_video {
+        return Err("output has no video stream".to_string());
+    }
+    let wants_audio = is_audio_only || args.iter().any(|a| a == "-c:a" || a == "-c");
+    if wants_audio && !info.has_audio {
+        // only an error if there was audio to begin with
+        let source = parse_media_info(probe_json(input.to_string()).await?)?;
+        if source.has_audio {
+            return Err("output has no audio stream".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Writes `<output>.json` next to the output so a conversion can be reproduced or debugged later.
+async fn write_conversion_report(
+    input_path: &str,
+    output_path: &str,
+    args: &[String],
+    encode_seconds: f64,
+) -> Result<(), String> {
+    // a probe failure shouldn't lose the rest of the report
+    let input = match probe_json(input_path.to_string()).await {
+        Ok(v) => parse_media_info(v).ok(),
+        Err(_) => None,
+    };
+    let output = match probe_json(output_path.to_string()).await {
+        Ok(v) => parse_media_info(v).ok(),
+        Err(_) => None,
+    };
+
+    let mut command = vec![resolve_tool("ffmpeg").to_string_lossy().to_string()];
+    command.extend(args.iter().cloned());
+    let finished_at_unix = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let report = ConversionReport {
+        command,
+        input,
+        output,
+        encode_seconds,
+        finished_at_unix,
+    };
+    let json = serde_json::to_string_pretty(&report).map_err(|e| format!("Failed to build report: {e}"))?;
+    std::fs::write(format!("{output_path}.json"), json).map_err(|e| format!("Failed to write report: {e}"))
+}
+
+#[tauri::command]
+async fn resume_conversion(window: tauri::Window, job_id: String) -> Result<(), String> {
+    let options = load_resume_state(&window, &job_id)?;
+    // the job may have been saved before safe mode was turned on
+    ensure_safe_options(&window, &options)?;
+    let info = parse_media_info(probe_json(options.output_path.clone()).await?)
+        .map_err(|e| format!("Partial output can't be resumed, restart the conversion instead: {e}"))?;
+    // the partial file itself is the segment marker: whatever ffmpeg managed to write is kept
+    let completed_ms = (info.duration_seconds * 1000.0).floor() as u64;
+    let _slot = acquire_conversion_slot(&window).await?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let output = PathBuf::from(&options.output_path);
+        let first_part = part_path(&output, 1);
+        let second_part = part_path(&output, 2);
+
+        let resume_start = options.start_ms + completed_ms;
+        if resume_start >= options.end_ms {
+            return save_resume_state(&window, &job_id, None);
+        }
+
+        std::fs::rename(&output, &first_part)
+            .map_err(|e| format!("Failed to move partial output: {}", e))?;
+
+        let rest = ConversionOptions {
+            start_ms: resume_start,
+            output_path: second_part.to_string_lossy().to_string(),
+            ..options.clone()
+        };
+        let args = build_ffmpeg_args(&rest).map_err(|e| format!("Argument error: {}", e))?;
+        if let Err(e) = run_ffmpeg_at(&window, &args, options.priority.as_deref()) {
+            // put things back so another resume attempt starts from the same point
+            let _ = std::fs::remove_file(&second_part);
+            let _ = std::fs::rename(&first_part, &output);
+            return Err(e.into());
+        }
+
+        let list_path = work_dir(&window).join(format!("{job_id}.concat.txt"));
+        let list = [&first_part, &second_part]
+            .iter()
+            .map(|p| format!("file '{}'\n", p.to_string_lossy().replace('\'', "'\\''")))
+            .collect::<String>();
+        std::fs::write(&list_path, list).map_err(|e| format!("Failed to write concat list: {}", e))?;
+
+        let concat_args: Vec<String> = vec![
+            "-y".to_string(),
+            "-f".to_string(),
+            "concat".to_string(),
+            "-safe".to_string(),
+            "0".to_string(),
+            "-i".to_string(),
+            list_path.to_string_lossy().to_string(),
+            "-c".to_string(),
+            "copy".to_string(),
+            options.output_path.clone(),
+        ];
+        let joined = run_ffmpeg_at(&window, &concat_args, options.priority.as_deref());
+        let _ = std::fs::remove_file(&list_path);
+        joined?;
+
+        let _ = std::fs::remove_file(&first_part);
+        let _ = std::fs::remove_file(&second_part);
+        save_resume_state(&window, &job_id, None)
+    }).await.map_err(|e| format!("Task failed: {}", e))??;
+
+    Ok(())
+}
+
+const RESUME_STORE_FILE: &str = "resume.json";
+const SETTINGS_STORE_FILE: &str = "settings.json";
+const DEFAULT_OUTPUT_TEMPLATE: &str = "{dir}/{name}_converted.{ext}";
+
+fn save_resume_state(
+    window: &tauri::Window,
+    job_id: &str,
+    options: Option<&ConversionOptions>,
+) -> Result<(), String> {
+    let store = window
+        .store(RESUME_STORE_FILE)
+        .map_err(|e| format!("Failed to open resume store: {e}"))?;
+    match options {
+        Some(opts) => {
+            let value = serde_json::to_value(opts).map_err(|e| format!("Failed to save resume state: {e}"))?;
+            store.set(job_id, value);
+        }
+        None => {
+            store.delete(job_id);
+        }
+    }
+    store.save().map_err(|e| format!("Failed to save resume store: {e}"))
+}
+
+fn load_resume_state(window: &tauri::Window, job_id: &str) -> Result<ConversionOptions, String> {
+    let store = window
+        .store(RESUME_STORE_FILE)
+        .map_err(|e| format!("Failed to open resume store: {e}"))?;
+    let value = store
+        .get(job_id)
+        .ok_or_else(|| format!("No interrupted conversion found for job {job_id}"))?;
+    serde_json::from_value(value).map_err(|e| format!("Corrupt resume state for job {job_id}: {e}"))
+}
+
+#[tauri::command]
+fn suggest_output_path(window: tauri::Window, input_path: String, format: String) -> Result<String, String> {
+    let store = window
+        .store(SETTINGS_STORE_FILE)
+        .map_err(|e| format!("Failed to open settings: {e}"))?;
+    let template = store
+        .get("outputTemplate")
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_else(|| DEFAULT_OUTPUT_TEMPLATE.to_string());
+    let input = PathBuf::from(&input_path);
+    let dir = output_dir_for(&window, &input)?;
+    let name = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+
+    let path = render_output_template(&template, &dir, &name, &format)?;
+    Ok(avoid_collision(path).to_string_lossy().to_string())
+}
+
+/// Settings' defaultOutputDir, or the input's own folder when that isn't set.
+fn output_dir_for(window: &tauri::Window, input: &std::path::Path) -> Result<String, String> {
+    let store = window
+        .store(SETTINGS_STORE_FILE)
+        .map_err(|e| format!("Failed to open settings: {e}"))?;
+    let default_dir = store
+        .get("defaultOutputDir")
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .filter(|d| !d.is_empty());
+    Ok(default_dir.unwrap_or_else(|| {
+        input
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }))
+}
+
+/// Names the output from the input's tags, e.g. "{artist} - {title}.{ext}". Any tag ffprobe
+/// reports can be used; {name} is the input file name and {ext} the output format. Missing tags
+/// render as "Unknown".
+#[tauri::command]
+async fn suggest_tagged_output_path(
+    window: tauri::Window,
+    input_path: String,
+    format: String,
+    template: String,
+) -> Result<String, String> {
+    ensure_safe_paths(&window, &[&input_path], &[])?;
+    let input = PathBuf::from(&input_path);
+    let dir = output_dir_for(&window, &input)?;
+    let tags = parse_media_tags(&probe_json(input_path).await?)?;
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+
+    let file_name = render_tag_template(&template, &tags, &stem, &format)?;
+    Ok(avoid_collision(PathBuf::from(dir).join(file_name)).to_string_lossy().to_string())
+}
+
+fn render_tag_template(template: &str, tags: &MediaTags, stem: &str, ext: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .map(|c| open + c)
+            .ok_or_else(|| format!("Unclosed placeholder in template: {template}"))?;
+        out.push_str(&rest[..open]);
+        let key = &rest[open + 1..close];
+        let value = match key {
+            "name" => stem.to_string(),
+            "ext" => ext.to_string(),
+            _ => lookup_tag(tags, key).unwrap_or_else(|| "Unknown".to_string()),
+        };
+        // ext stays as is, everything else comes from user data and can hold anything
+        if key == "ext" {
+            out.push_str(&value);
+        } else {
+            out.push_str(&sanitize_file_component(&value));
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    if out.trim().is_empty() {
+        return Err("Output template rendered an empty file name".to_string());
+    }
+    Ok(out)
+}
+
+// Tag keys are case-insensitive in practice (ARTIST in flac/ogg, artist in mp3/mp4), and
+// ogg/opus keep them on the audio stream instead of the container.
+fn lookup_tag(tags: &MediaTags, key: &str) -> Option<String> {
+    std::iter::once(&tags.format)
+        .chain(tags.streams.iter().map(|s| &s.tags))
+        .find_map(|map| {
+            map.iter()
+                .find(|(k, v)| k.eq_ignore_ascii_case(key) && !v.trim().is_empty())
+                .map(|(_, v)| v.trim().to_string())
+        })
+}
+
+// Windows device names that can't be used as a file name, with or without an extension.
+const RESERVED_WINDOWS_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn sanitize_file_component(value: &str) -> String {
+    let illegal: &[char] = if cfg!(windows) {
+        &['<', '>', ':', '"', '/', '\\', '|', '?', '*']
+    } else if cfg!(target_os = "macos") {
+        &['/', ':']
+    } else {
+        &['/']
+    };
+    let cleaned: String = value
+        .chars()
+        .map(|c| if c.is_control() || illegal.contains(&c) { '_' } else { c })
+        .collect();
+    if !cfg!(windows) {
+        return cleaned;
+    }
+    // Explorer silently drops trailing dots and spaces, so the name wouldn't match
+    let cleaned = cleaned.trim_end_matches(['.', ' ']).to_string();
+    if RESERVED_WINDOWS_NAMES.iter().any(|r| r.eq_ignore_ascii_case(&cleaned)) {
+        format!("{cleaned}_")
+    } else {
+        cleaned
+    }
+}
+
+/// Fills in a ConversionOptions for a plain-English goal so the user doesn't have to know
+/// mp4/h264 from webm/vp9. output_path is left empty; the UI gets that from suggest_output_path.
+#[tauri::command]
+fn recommend_settings(input_path: String, goal: String, info: MediaInfo) -> Result<ConversionOptions, String> {
+    recommend_for_goal(&goal, &info).map(|mut options| {
+        options.input_path = input_path;
+        options.end_ms = (info.duration_seconds * 1000.0).round() as u64;
+        options
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct SizeEstimate {
+    bytes: u64,
+    // CRF and stream copy sizes depend on the content, so they come with a spread
+    low_bytes: u64,
+    high_bytes: u64,
+    exact_bitrate: bool,
+}
+
+/// Instant size guess for the sliders, no encoding involved: bitrate * duration when the bitrate
+/// is fixed, a bits-per-pixel rule of thumb for CRF.
+#[tauri::command]
+fn estimate_output_size(options: ConversionOptions, info: MediaInfo) -> Result<SizeEstimate, String> {
+    let format = options.format.as_deref().unwrap_or("mp4");
+    if format == "gif" {
+        return Err("GIF size depends on the palette and can't be estimated".to_string());
+    }
+    let duration = expected_output_duration(&options);
+    let audio_kbps = if !info.has_audio { 0.0 } else { estimated_audio_kbps(&options, format, &info) };
+
+    let (video_kbps, spread) = if options.is_audio_only || !info.has_video {
+        (0.0, 1.0)
+    } else if options.copy_streams || options.copy_video {
+        // the source average minus its own audio, roughly
+        let source = info.bitrate_kbps.unwrap_or(0) as f64;
+        ((source - audio_kbps).max(0.0), 1.25)
+    } else if let Some(vb) = options.video_bitrate_kbps {
+        (vb as f64, 1.0)
+    } else {
+        let codec = options.video_codec.as_deref().or(video_codecs_for_format(format).first().copied()).unwrap_or("libx264");
+        (crf_video_kbps(&options, codec, &info), 2.0)
+    };
+    let video_kbps = match options.max_bitrate_kbps {
+        Some(max) => video_kbps.min(max as f64),
+        None => video_kbps,
+    };
+
+    let bytes_for = |kbps: f64| (kbps * 1000.0 * duration / 8.0).round() as u64;
+    let total = video_kbps + audio_kbps;
+    Ok(SizeEstimate {
+        bytes: bytes_for(total),
+        low_bytes: bytes_for(video_kbps / spread + audio_kbps),
+        high_bytes: bytes_for(video_kbps * spread + audio_kbps),
+        exact_bitrate: spread == 1.0,
+    })
+}
+
+fn estimated_audio_kbps(options: &ConversionOptions, format: &str, info: &MediaInfo) -> f64 {
+    if let Some(ab) = options.audio_bitrate_kbps {
+        return ab as f64;
+    }
+    let codec = options.audio_codec.as_deref().or(audio_codecs_for_format(format).first().copied()).unwrap_or("aac");
+    let rate = options.sample_rate.or(info.sample_rate).unwrap_or(48_000) as f64;
+    let channels = options.channels.unwrap_or(2) as f64;
+    let pcm_kbps = |bits: f64| rate * channels * bits / 1000.0;
+    match codec {
+        "pcm_s16le" => pcm_kbps(16.0),
+        "pcm_s24le" => pcm_kbps(24.0),
+        "pcm_f32le" => pcm_kbps(32.0),
+        // flac usually lands around 60% of the 16-bit pcm size
+        "flac" | "alac" => pcm_kbps(16.0) * 0.6,
+        _ => 128.0,
+    }
+}
+
+// Bits per pixel per frame at the encoder's default CRF, halving every 6 CRF steps. Calibrated on
+// typical 1080p30 footage: x264 at crf 23 is ~5 Mbps, x265 at 28 and vp9/av1 defaults about half that.
+fn crf_video_kbps(options: &ConversionOptions, codec: &str, info: &MediaInfo) -> f64 {
+    let (default_crf, base_bpp) = match codec {
+        "libx264" => (23.0, 0.08),
+        "libx265" => (28.0, 0.04),
+        "libvpx-vp9" | "libaom-av1" | "libsvtav1" => (32.0, 0.04),
+        _ => (23.0, 0.1),
+    };
+    let params = match codec {
+        "libx264" => options.x264_params.as_deref(),
+        "libx265" => options.x265_params.as_deref(),
+        _ => None,
+    };
+    let crf = params
+        .and_then(|p| p.split(':').find_map(|kv| kv.strip_prefix("crf=")))
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(default_crf);
+    let bpp = base_bpp * 2f64.powf((default_crf - crf) / 6.0);
+
+    let (width, height) = match (options.width, options.height, options.scale_percent) {
+        (Some(w), Some(h), _) => (w as f64, h as f64),
+        (_, _, Some(percent)) => (
+            info.width.unwrap_or(1920) as f64 * percent / 100.0,
+            info.height.unwrap_or(1080) as f64 * percent / 100.0,
+        ),
+        _ => (info.width.unwrap_or(1920) as f64, info.height.unwrap_or(1080) as f64),
+    };
+    let fps = options.fps.or(info.fps).unwrap_or(30.0);
+    width * height * fps * bpp / 1000.0
+}
+
+fn recommend_for_goal(goal: &str, info: &MediaInfo) -> Result<ConversionOptions, String> {
+    if info.is_image {
+        return Err("Still images don't need a recommendation, pick an output format directly".to_string());
+    }
+    let mut options = ConversionOptions::default();
+
+    if !info.has_video {
+        options.is_audio_only = true;
+        let (format, codec, bitrate) = match goal {
+            "smallest" => ("opus", "libopus", Some(96)),
+            "quality" => ("flac", "flac", None),
+            "compatible" => ("mp3", "libmp3lame", Some(192)),
+            "fastest" => ("wav", "pcm_s16le", None),
+            other => return Err(format!("Unknown goal: {other}")),
+        };
+        options.format = Some(format.to_string());
+        options.audio_codec = Some(codec.to_string());
+        options.audio_bitrate_kbps = bitrate;
+        return Ok(options);
+    }
+
+    let (format, video_codec, audio_codec) = match goal {
+        "smallest" => ("webm", "libvpx-vp9", "libopus"),
+        "quality" => ("mkv", "libx265", "flac"),
+        // h264 + aac in mp4 at yuv420p (the default pix_fmt for x264 here) plays basically everywhere
+        "compatible" | "fastest" => ("mp4", "libx264", "aac"),
+        other => return Err(format!("Unknown goal: {other}")),
+    };
+    // sanity check against the codec tables so this can't drift out of sync with build_ffmpeg_args
+    if !video_codecs_for_format(format).contains(&video_codec) || !audio_codecs_for_format(format).contains(&audio_codec) {
+        return Err(format!("Recommended codecs don't fit {format}"));
+    }
+    options.format = Some(format.to_string());
+    options.video_codec = Some(video_codec.to_string());
+    options.audio_codec = Some(audio_codec.to_string());
+
+    match goal {
+        "smallest" => {
+            // vp9 holds up fine at roughly half of what the source spends
+            options.video_bitrate_kbps = info.bitrate_kbps.map(|b| (b / 2).max(300));
+            options.audio_bitrate_kbps = Some(96);
+        }
+        "quality" => {
+            options.x265_params = Some("crf=18".to_string());
+        }
+        "compatible" => {
+            options.preset = Some("medium".to_string());
+            options.audio_bitrate_kbps = Some(192);
+        }
+        _ => {
+            options.preset = Some("ultrafast".to_string());
+            options.audio_bitrate_kbps = Some(160);
+        }
+    }
+    if !info.has_audio {
+        options.audio_bitrate_kbps = None;
+    }
+    Ok(options)
+}
+
+fn render_output_template(template: &str, dir: &str, name: &str, ext: &str) -> Result<PathBuf, String> {
+    if !template.contains("{name}") {
+        return Err("Output template needs a {name} placeholder".to_string());
+    }
+    let rendered = template
+        .replace("{dir}", dir)
+        .replace("{name}", name)
+        .replace("{ext}", ext);
+    Ok(PathBuf::from(rendered))
+}
+
+/// Appends _1, _2, ... to the file name until it no longer points at an existing file.
+fn avoid_collision(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path.extension().map(|e| e.to_string_lossy().to_string());
+    (1..)
+        .map(|n| {
+            let file = match ext {
+                Some(ref e) => format!("{stem}_{n}.{e}"),
+                None => format!("{stem}_{n}"),
+            };
+            path.with_file_name(file)
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap_or(path)
+}
+
+/// Folder for intermediate files (concat lists, passes, benchmarks). Configurable because the
+/// system temp drive is often small or locked down.
+struct WorkDir(RwLock<PathBuf>);
+
+fn work_dir(window: &tauri::Window) -> PathBuf {
+    window
+        .state::<WorkDir>()
+        .0
+        .read()
+        .map(|dir| dir.clone())
+        .unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn ensure_writable_dir(dir: &std::path::Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Can't create {}: {e}", dir.display()))?;
+    let probe = dir.join(".xhmpeg-write-test");
+    std::fs::write(&probe, b"").map_err(|e| format!("{} is not writable: {e}", dir.display()))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Reads the saved temp dir at startup, falling back to the system default if it's gone or read-only.
+fn load_work_dir(app: &tauri::App) -> PathBuf {
+    let saved = app
+        .store(SETTINGS_STORE_FILE)
+        .ok()
+        .and_then(|store| store.get("tempDir"))
+        .and_then(|v| v.as_str().map(PathBuf::from));
+    match saved {
+        Some(dir) => match ensure_writable_dir(&dir) {
+            Ok(()) => dir,
+            Err(e) => {
+                println!("Ignoring temp dir setting: {e}");
+                std::env::temp_dir()
+            }
+        },
+        None => std::env::temp_dir(),
+    }
+}
+
+#[tauri::command]
+fn set_temp_dir(window: tauri::Window, path: Option<String>) -> Result<String, String> {
+    let dir = match path.as_deref().filter(|p| !p.is_empty()) {
+        Some(p) => {
+            let dir = PathBuf::from(p);
+            ensure_writable_dir(&dir)?;
+            dir
+        }
+        None => std::env::temp_dir(),
+    };
+
+    let store = window
+        .store(SETTINGS_STORE_FILE)
+        .map_err(|e| format!("Failed to open settings: {e}"))?;
+    match path.filter(|p| !p.is_empty()) {
+        Some(p) => store.set("tempDir", p),
+        None => {
+            store.delete("tempDir");
+        }
+    }
+    store.save().map_err(|e| format!("Failed to save settings: {e}"))?;
+
+    if let Ok(mut current) = window.state::<WorkDir>().0.write() {
+        *current = dir.clone();
+    }
+    Ok(dir.to_string_lossy().to_string())
+}
+
+/// Safe mode: when set, every file a conversion reads or writes has to live under this root, and
+/// the raw filter_complex escape hatch is off (filter graphs can open files through movie=/amovie=).
+struct SafeModeRoot(RwLock<Option<PathBuf>>);
+
+fn load_safe_mode_root(app: &tauri::App) -> Option<PathBuf> {
+    let root = app
+        .store(SETTINGS_STORE_FILE)
+        .ok()
+        .and_then(|store| store.get("safeModeRoot"))
+        .and_then(|v| v.as_str().map(PathBuf::from))?;
+    // a root that's gone would otherwise reject everything without saying why
+    match root.canonicalize() {
+        Ok(root) => Some(root),
+        Err(e) => {
+            println!("Ignoring safe mode root {}: {e}", root.display());
+            None
+        }
+    }
+}
+
+#[tauri::command]
+fn set_safe_mode_root(window: tauri::Window, root: Option<String>) -> Result<Option<String>, String> {
+    let root = match root.filter(|r| !r.is_empty()) {
+        Some(r) => Some(
+            PathBuf::from(&r)
+                .canonicalize()
+                .map_err(|e| format!("Safe mode root {r} is not usable: {e}"))?,
+        ),
+        None => None,
+    };
+
+    let store = window
+        .store(SETTINGS_STORE_FILE)
+        .map_err(|e| format!("Failed to open settings: {e}"))?;
+    match root {
+        Some(ref r) => store.set("safeModeRoot", r.to_string_lossy().to_string()),
+        None => {
+            store.delete("safeModeRoot");
+        }
+    }
+    store.save().map_err(|e| format!("Failed to save settings: {e}"))?;
+
+    if let Ok(mut current) = window.state::<SafeModeRoot>().0.write() {
+        *current = root.clone();
+    }
+    Ok(root.map(|r| r.to_string_lossy().to_string()))
+}
+
+fn safe_mode_root(window: &tauri::Window) -> Option<PathBuf> {
+    window.state::<SafeModeRoot>().0.read().ok().and_then(|r| r.clone())
+}
+
+fn ensure_safe_options(window: &tauri::Window, options: &ConversionOptions) -> Result<(), String> {
+    let Some(root) = safe_mode_root(window) else {
+        return Ok(());
+    };
+    if options.filter_complex.is_some() {
+        return Err("filter_complex is disabled in safe mode".to_string());
+    }
+    ensure_within_root(&options.output_path, &root, false)?;
+    let inputs = [
+        Some(&options.input_path),
+        options.cover_image.as_ref(),
+        options.import_chapters.as_ref(),
+        options.spatial_sofa_file.as_ref(),
+        options.timecode_font_file.as_ref(),
+    ];
+    for path in inputs.into_iter().flatten() {
+        ensure_within_root(path, &root, true)?;
+    }
+    // encoder params like stats=, qpfile= or csv= read and write files of their own
+    for value in option_values_naming_files(options) {
+        ensure_within_root(value, &root, false)?;
+    }
+    Ok(())
+}
+
+// x264/x265 keys whose value is a file; anything else is only treated as a path when it looks like one
+const FILE_PARAM_KEYS: [&str; 14] = [
+    "stats", "qpfile", "cqmfile", "tcfile-in", "tcfile-out", "dump-yuv", "csv", "analysis-save", "analysis-load",
+    "recon", "zonefile", "scaling-list", "lambda-file", "dolby-vision-rpu",
+];
+
+fn option_values_naming_files(options: &ConversionOptions) -> Vec<&str> {
+    let looks_like_path = |v: &str| v.contains('/') || v.contains('\\');
+    let params = [options.x264_params.as_deref(), options.x265_params.as_deref()]
+        .into_iter()
+        .flatten()
+        .flat_map(|p| p.split(':'))
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(key, value)| FILE_PARAM_KEYS.contains(key) || looks_like_path(value))
+        .map(|(_, value)| value);
+    let muxer = options
+        .muxer_options
+        .iter()
+        .flat_map(|m| m.values())
+        .map(String::as_str)
+        .filter(|value| looks_like_path(value));
+    params.chain(muxer).collect()
+}
+
+/// Safe mode check for the commands that take their paths directly rather than as ConversionOptions.
+fn ensure_safe_paths(window: &tauri::Window, inputs: &[&str], outputs: &[&str]) -> Result<(), String> {
+    ensure_paths_within(safe_mode_root(window).as_deref(), inputs, outputs)
+}
+
+fn ensure_paths_within(root: Option<&std::path::Path>, inputs: &[&str], outputs: &[&str]) -> Result<(), String> {
+    let Some(root) = root else {
+        return Ok(());
+    };
+    for path in inputs {
+        ensure_within_root(path, root, true)?;
+    }
+    for path in outputs {
+        ensure_within_root(path, root, false)?;
+    }
+    Ok(())
+}
+
+// Symlinks are resolved before comparing, so a link inside the root can't point outside it. Files
+// that don't exist yet (outputs) are checked through their parent folder.
+fn ensure_within_root(path: &str, root: &std::path::Path, must_exist: bool) -> Result<(), String> {
+    let candidate = std::path::Path::new(path);
+    if !candidate.is_absolute() || candidate.components().any(|c| c == std::path::Component::ParentDir) {
+        return Err(format!("Safe mode only accepts absolute paths without '..': {path}"));
+    }
+    let resolved = match (candidate.canonicalize(), must_exist) {
+        (Ok(resolved), _) => resolved,
+        (Err(e), true) => return Err(format!("Can't resolve {path}: {e}")),
+        (Err(_), false) => {
+            let parent = candidate.parent().ok_or_else(|| format!("{path} has no parent folder"))?;
+            let name = candidate.file_name().ok_or_else(|| format!("{path} has no file name"))?;
+            parent
+                .canonicalize()
+                .map_err(|e| format!("Can't resolve the folder of {path}: {e}"))?
+                .join(name)
+        }
+    };
+    if !resolved.starts_with(root) {
+        return Err(format!("{path} is outside the allowed folder {}", root.display()));
+    }
+    Ok(())
+}
+
+const X264_PRESETS: [&str; 10] = [
+    "ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow", "placebo",
+];
+
+/// x264 preset used when a job doesn't set its own; None means ffmpeg's "medium".
+struct DefaultPreset(RwLock<Option<String>>);
+
+fn load_default_preset(app: &tauri::App) -> Option<String> {
+    let saved = app
+        .store(SETTINGS_STORE_FILE)
+        .ok()
+        .and_then(|store| store.get("defaultPreset"))
+        .and_then(|v| v.as_str().map(str::to_string))?;
+    if X264_PRESETS.contains(&saved.as_str()) {
+        Some(saved)
+    } else {
+        println!("Ignoring unknown default preset: {saved}");
+        None
+    }
+}
+
+#[tauri::command]
+fn set_default_preset(window: tauri::Window, preset: Option<String>) -> Result<String, String> {
+    let preset = preset.filter(|p| !p.is_empty());
+    if let Some(ref p) = preset {
+        if !X264_PRESETS.contains(&p.as_str()) {
+            return Err(format!("Unknown preset: {p} (use one of {})", X264_PRESETS.join(", ")));
+        }
+    }
+
+    let store = window
+        .store(SETTINGS_STORE_FILE)
+        .map_err(|e| format!("Failed to open settings: {e}"))?;
+    match preset {
+        Some(ref p) => store.set("defaultPreset", p.clone()),
+        None => {
+            store.delete("defaultPreset");
+        }
+    }
+    store.save().map_err(|e| format!("Failed to save settings: {e}"))?;
+
+    if let Ok(mut current) = window.state::<DefaultPreset>().0.write() {
+        *current = preset.clone();
+    }
+    Ok(preset.unwrap_or_else(|| "medium".to_string()))
+}
+
+// the per-job preset always wins
+fn apply_default_preset(window: &tauri::Window, options: &mut ConversionOptions) {
+    if options.preset.is_none() {
+        options.preset = window.state::<DefaultPreset>().0.read().ok().and_then(|p| p.clone());
+    }
+}
+
+/// Caps how many conversions run ffmpeg at once; the rest wait in run_conversion for a permit.
+/// Swapped out wholesale when the limit changes, jobs already running keep their old permit.
+struct ConversionSlots(RwLock<Arc<Semaphore>>);
+
+// std only reports logical threads, so half of that stands in for the physical core count
+fn default_max_conversions() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| (n.get() / 2).max(1))
+        .unwrap_or(1)
+}
+
+fn load_max_conversions(app: &tauri::App) -> usize {
+    app.store(SETTINGS_STORE_FILE)
+        .ok()
+        .and_then(|store| store.get("maxConcurrentConversions"))
+        .and_then(|v| v.as_u64())
+        .filter(|&n| n > 0)
+        .map(|n| n as usize)
+        .unwrap_or_else(default_max_conversions)
+}
+
+async fn acquire_conversion_slot(window: &tauri::Window) -> Result<tokio::sync::OwnedSemaphorePermit, String> {
+    let slots = window
+        .state::<ConversionSlots>()
+        .0
+        .read()
+        .map(|s| s.clone())
+        .map_err(|_| "Conversion queue is unavailable".to_string())?;
+    slots
+        .acquire_owned()
+        .await
+        .map_err(|e| format!("Conversion queue closed: {e}"))
+}
+
+#[tauri::command]
+fn set_max_concurrent_conversions(window: tauri::Window, limit: Option<usize>) -> Result<usize, String> {
+    let store = window
+        .store(SETTINGS_STORE_FILE)
+        .map_err(|e| format!("Failed to open settings: {e}"))?;
+    let limit = match limit {
+        Some(0) => return Err("At least one conversion has to be allowed".to_string()),
+        Some(n) => {
+            store.set("maxConcurrentConversions", n);
+            n
+        }
+        None => {
+            store.delete("maxConcurrentConversions");
+            default_max_conversions()
+        }
+    };
+    store.save().map_err(|e| format!("Failed to save settings: {e}"))?;
+
+    if let Ok(mut current) = window.state::<ConversionSlots>().0.write() {
+        *current = Arc::new(Semaphore::new(limit));
+    }
+    Ok(limit)
+}
+
+fn part_path(output: &std::path::Path, part: u32) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    let ext = output
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    output.with_file_name(format!("{stem}.part{part}.{ext}"))
+}
+
+#[tauri::command]
+async fn make_proxy(
+    window: tauri::Window,
+    input_path: String,
+    output_path: Option<String>,
+    // proxies are usually made in the background while editing, so "low" is a good fit
+    priority: Option<String>,
+) -> Result<String, String> {
+    let output_path = output_path.unwrap_or_else(|| proxy_path_for(&input_path));
+    ensure_safe_paths(&window, &[&input_path], &[&output_path])?;
+    let info = parse_media_info(probe_json(input_path.clone()).await?)?;
+    if !info.has_video {
+        return Err("Proxies can only be made from video files".to_string());
+    }
+
+    let (width, height) = match (info.width, info.height) {
+        (Some(w), Some(h)) if h > PROXY_HEIGHT => {
+            let scaled = (w as f64 * PROXY_HEIGHT as f64 / h as f64).round() as u32;
+            (Some(scaled + scaled % 2), Some(PROXY_HEIGHT))
+        }
+        (w, h) => (w, h),
+    };
+
+    // fps is left unset so the proxy keeps the source timing and lines up with the original
+    let options = ConversionOptions {
+        input_path,
+        output_path: output_path.clone(),
+        start_ms: 0,
+        end_ms: (info.duration_seconds * 1000.0).round() as u64,
+        width,
+        height,
+        video_bitrate_kbps: Some(PROXY_VIDEO_BITRATE_KBPS),
+        audio_bitrate_kbps: Some(128),
+        format: Some("mp4".to_string()),
+        video_codec: Some("libx264".to_string()),
+        audio_codec: Some("aac".to_string()),
+        preset: Some("ultrafast".to_string()),
+        priority,
+        ..Default::default()
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let args = build_ffmpeg_args(&options).map_err(|e| format!("Argument error: {}", e))?;
+        run_ffmpeg_at(&window, &args, options.priority.as_deref())
+    }).await.map_err(|e| format!("Task failed: {}", e))??;
+
+    Ok(output_path)
+}
+
+const PROXY_HEIGHT: u32 = 720;
+const PROXY_VIDEO_BITRATE_KBPS: u64 = 2000;
+
+fn proxy_path_for(input_path: &str) -> String {
+    let input = PathBuf::from(input_path);
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    input
+        .with_file_name(format!("{stem}_proxy.mp4"))
+        .to_string_lossy()
+        .to_string()
+}
+
+#[derive(Debug, Default)]
+struct FfmpegStats {
+    dropped_frames: Option<u64>,
+    duplicated_frames: Option<u64>,
+}
+
+// ffmpeg only prints dup=/drop= once they're non-zero, so a stats line without them means zero
+fn parse_frame_stats(line: &str) -> FfmpegStats {
+    let counter = |key: &str| {
+        line.split_once(key)
+            .and_then(|(_, rest)| parse_leading_number(rest))
+            .map(|n| n as u64)
+            .unwrap_or(0)
+    };
+    FfmpegStats {
+        dropped_frames: Some(counter("drop=")),
+        duplicated_frames: Some(counter("dup=")),
+    }
+}
+
+fn run_ffmpeg(window: &tauri::Window, args: &[String]) -> Result<FfmpegStats, ConversionError> {
+    run_ffmpeg_at(window, args, None)
+}
+
+fn run_ffmpeg_at(window: &tauri::Window, args: &[String], priority: Option<&str>) -> Result<FfmpegStats, ConversionError> {
+    let nice = nice_level(priority).map_err(|e| ConversionError::new(ErrorCode::InvalidOptions, e))?;
+    let capabilities = window.state::<FfmpegCapabilities>();
+    if let Some(version) = capabilities.version() {
+        ensure_version_supports(args, version).map_err(|e| ConversionError::new(ErrorCode::UnsupportedCodec, e))?;
+    }
+    if let Some(available) = capabilities.encoders() {
+        ensure_encoders_available(args, available).map_err(|e| ConversionError::new(ErrorCode::UnsupportedCodec, e))?;
+    }
+    if let Some(available) = capabilities.filters() {
+        ensure_filters_available(args, available).map_err(|e| ConversionError::new(ErrorCode::UnsupportedCodec, e))?;
+    }
+    if let Some(available) = capabilities.hwaccels() {
+        ensure_hwaccel_available(args, available).map_err(|e| ConversionError::new(ErrorCode::UnsupportedCodec, e))?;
+    }
+
+    // start it under nice so it never runs a moment at normal priority
+    #[cfg(unix)]
+    let mut cmd = if nice > 0 {
+        let mut cmd = Command::new("nice");
+        cmd.arg("-n").arg(nice.to_string()).arg(resolve_tool("ffmpeg"));
+        cmd
+    } else {
+        Command::new(resolve_tool("ffmpeg"))
+    };
+    #[cfg(not(unix))]
+    let mut cmd = Command::new(resolve_tool("ffmpeg"));
+    cmd.args(args)
+        .stdout(Stdio::piped()) // just in case i need it
+        .stderr(Stdio::piped());
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x4000;
+        const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x8000;
+        match nice {
+            n if n > 0 => {
+                cmd.creation_flags(BELOW_NORMAL_PRIORITY_CLASS);
+            }
+            n if n < 0 => {
+                cmd.creation_flags(ABOVE_NORMAL_PRIORITY_CLASS);
+            }
+            _ => {}
+        }
+    }
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| {
+            let code = match e.kind() {
+                std::io::ErrorKind::NotFound => ErrorCode::FileNotFound,
+                std::io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+                _ => ErrorCode::Unknown,
+            };
+            ConversionError::new(code, format!("Failed to spawn: {}", e))
+        })?;
+
+    let stderr = child.stderr.take().ok_or("Failed to open stderr")?;
+    let mut reader = BufReader::new(stderr);
+
+    let mut last_stats: Option<String> = None;
+    let mut tail: VecDeque<String> = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    let mut last_emit: Option<std::time::Instant> = None;
+    // stats line held back by the throttle, sent once the interval is up or ffmpeg exits
+    let mut pending: Option<String> = None;
+
+    // record by record stream of output
+    loop {
+        let text = match read_stderr_record(&mut reader) {
+            Ok(Some(text)) => text,
+            Ok(None) => break,
+            Err(e) => {
+                println!("Error: {}", e);
+                break;
+            }
+        };
+        if text.trim().is_empty() {
+            continue;
+        }
+        println!("{}", text);
+        // fast encodes print stats far more often than the UI can redraw, so those get
+        // coalesced; everything else (warnings, errors) goes out right away
+        if text.contains("frame=") {
+            last_stats = Some(text.clone());
+            if last_emit.is_none_or(|t| t.elapsed() >= PROGRESS_EMIT_INTERVAL) {
+                window.emit("PROGRESS", text).unwrap();
+                last_emit = Some(std::time::Instant::now());
+                pending = None;
+            } else {
+                pending = Some(text);
+            }
+            continue;
+        }
+        // a held back stats record goes out first so the UI sees things in ffmpeg's order
+        if let Some(stats) = pending.take() {
+            window.emit("PROGRESS", stats).unwrap();
+            last_emit = Some(std::time::Instant::now());
+        }
+        // stats records stay out of the tail, they'd push the actual error out of it
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(text.clone());
+        window.emit("PROGRESS", text).unwrap();
+    }
+
+    // the final stats are never throttled away, the UI needs them to reach 100%
+    if let Some(text) = pending {
+        window.emit("PROGRESS", text).unwrap();
+    }
+
+    let status = child.wait().map_err(|e| format!("Waiting failed: {}", e))?;
+
+    if status.success() {
+        Ok(last_stats.as_deref().map(parse_frame_stats).unwrap_or_default()) // last line, dont add semicolon
+    } else {
+        Err(classify_ffmpeg_failure(status.code(), &tail))
+    }
+}
+
+const STDERR_TAIL_LINES: usize = 20;
+const PROGRESS_EMIT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Next stderr record, ended by either \r or \n: ffmpeg rewrites its stats line with \r, so
+/// splitting on \n alone would bunch a whole encode's worth of stats into one line. None at EOF.
+fn read_stderr_record(reader: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut record = Vec::new();
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok((!record.is_empty()).then(|| String::from_utf8_lossy(&record).to_string()));
+        }
+        match available.iter().position(|&b| b == b'\r' || b == b'\n') {
+            Some(end) => {
+                record.extend_from_slice(&available[..end]);
+                reader.consume(end + 1);
+                return Ok(Some(String::from_utf8_lossy(&record).to_string()));
+            }
+            None => {
+                let len = available.len();
+                record.extend_from_slice(available);
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+enum ErrorCode {
+    FileNotFound,
+    UnsupportedCodec,
+    PermissionDenied,
+    DiskFull,
+    Cancelled,
+    InvalidOptions,
+    Unknown,
+}
+
+/// Structured error for conversions; `code` lets the frontend react without parsing messages.
+#[derive(Debug, Serialize)]
+pub struct ConversionError {
+    code: ErrorCode,
+    message: String,
+    exit_code: Option<i32>,
+}
+
+impl ConversionError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ConversionError {
+            code,
+            message: message.into(),
+            exit_code: None,
+        }
+    }
+}
+
+impl From<String> for ConversionError {
+    fn from(message: String) -> Self {
+        ConversionError::new(ErrorCode::Unknown, message)
+    }
+}
+
+impl From<&str> for ConversionError {
+    fn from(message: &str) -> Self {
+        ConversionError::new(ErrorCode::Unknown, message)
+    }
+}
+
+// lets the commands that still report plain strings keep using `?` on run_ffmpeg
+impl From<ConversionError> for String {
+    fn from(err: ConversionError) -> Self {
+        err.message
+    }
+}
+
+// Matched against ffmpeg's stderr in order; the first hit wins.
+const ERROR_PATTERNS: &[(&str, ErrorCode)] = &[
+    ("No such file or directory", ErrorCode::FileNotFound),
+    ("Permission denied", ErrorCode::PermissionDenied),
+    ("Access is denied", ErrorCode::PermissionDenied),
+    ("No space left on device", ErrorCode::DiskFull),
+    ("There is not enough space on the disk", ErrorCode::DiskFull),
+    ("Unknown encoder", ErrorCode::UnsupportedCodec),
+    ("Encoder not found", ErrorCode::UnsupportedCodec),
+    ("Error while opening encoder", ErrorCode::UnsupportedCodec),
+    ("Could not find tag for codec", ErrorCode::UnsupportedCodec),
+    ("not currently supported in container", ErrorCode::UnsupportedCodec),
+    // hardware encoders that exist in the build but can't reach a working device/driver
+    ("No capable devices found", ErrorCode::UnsupportedCodec),
+    ("OpenEncodeSessionEx failed", ErrorCode::UnsupportedCodec),
+    ("Cannot load nvcuda.dll", ErrorCode::UnsupportedCodec),
+    ("Failed to initialise VAAPI", ErrorCode::UnsupportedCodec),
+    ("Exiting normally, received signal", ErrorCode::Cancelled),
+];
+
+fn classify_ffmpeg_failure(exit_code: Option<i32>, stderr_tail: &VecDeque<String>) -> ConversionError {
+    let code = stderr_tail
+        .iter()
+        .find_map(|line| {
+            ERROR_PATTERNS
+                .iter()
+                .find(|(pattern, _)| line.contains(pattern))
+                .map(|(_, code)| *code)
+        })
+        // 255 is what ffmpeg exits with when it's interrupted, and no code at all means it was killed
+        .or(match exit_code {
+            Some(255) | None => Some(ErrorCode::Cancelled),
+            _ => None,
+        })
+        .unwrap_or(ErrorCode::Unknown);
+
+    let detail = stderr_tail
+        .iter()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .map(|l| l.trim().to_string())
+        .unwrap_or_default();
+    let message = match exit_code {
+        Some(c) => format!("ffmpeg failed (exit code {c}): {detail}"),
+        None => format!("ffmpeg was terminated: {detail}"),
+    };
+
+    ConversionError {
+        code,
+        message,
+        exit_code,
+    }
+}
+
+const HOVER_PREVIEW_WIDTH: u32 = 320;
+const HOVER_PREVIEW_MAX_WIDTH: u32 = 480;
+const HOVER_PREVIEW_SECS: f64 = 3.0;
+const HOVER_PREVIEW_MAX_SECS: f64 = 6.0;
+const HOVER_PREVIEW_FPS: f64 = 10.0;
+
+/// Scrubbing thumbnails for web players: one frame every interval_secs tiled into a single image,
+/// plus a WebVTT file pointing each time range at its tile with a #xywh= fragment.
+#[tauri::command]
+async fn generate_sprite_sheet(window: tauri::Window, options: SpriteSheetOptions) -> Result<SpriteSheet, String> {
+    ensure_safe_paths(&window, &[&options.input_path], &[&options.output_image, &options.output_vtt])?;
+    if !options.interval_secs.is_finite() || options.interval_secs < 0.1 {
+        return Err(format!("Thumbnail interval must be at least 0.1s, got {}", options.interval_secs));
+    }
+    if options.columns == 0 || !(16..=1920).contains(&options.thumb_width) {
+        return Err("Sprite sheets need at least one column and a thumbnail width of 16-1920".to_string());
+    }
+    let info = parse_media_info(probe_json(options.input_path.clone()).await?)?;
+    if !info.has_video || info.is_image {
+        return Err("Sprite sheets need a video source".to_string());
+    }
+    let (Some(w), Some(h)) = (info.width, info.height) else {
+        return Err("Source has no frame size".to_string());
+    };
+    let thumb_w = options.thumb_width - options.thumb_width % 2;
+    let scaled = (h as f64 * thumb_w as f64 / w as f64).round() as u32;
+    let thumb_h = (scaled + scaled % 2).max(2);
+
+    let count = (info.duration_seconds / options.interval_secs).ceil().max(1.0) as u32;
+    if count > MAX_SPRITE_THUMBNAILS {
+        return Err(format!(
+            "{count} thumbnails is more than the {MAX_SPRITE_THUMBNAILS} a sheet can hold, use a longer interval"
+        ));
+    }
+    let columns = options.columns.min(count);
+    let rows = count.div_ceil(columns);
+    let (sheet_w, sheet_h) = (columns as u64 * thumb_w as u64, rows as u64 * thumb_h as u64);
+    if sheet_w > MAX_SPRITE_SIDE || sheet_h > MAX_SPRITE_SIDE {
+        return Err(format!(
+            "A {sheet_w}x{sheet_h} sheet is over the {MAX_SPRITE_SIDE}px an image side can be, use fewer columns, smaller thumbnails or a longer interval"
+        ));
+    }
+
+    let args: Vec<String> = vec![
+        "-y".to_string(),
+        "-i".to_string(),
+        options.input_path.clone(),
+        "-vf".to_string(),
+        format!("fps=1/{},scale={thumb_w}:{thumb_h},tile={columns}x{rows}", options.interval_secs),
+        "-frames:v".to_string(),
+        "1".to_string(),
+        "-an".to_string(),
+        options.output_image.clone(),
+    ];
+    tauri::async_runtime::spawn_blocking(move || run_ffmpeg(&window, &args))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+
+    // players resolve the image relative to the vtt, which normally sits next to it
+    let image_ref = std::path::Path::new(&options.output_image)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| options.output_image.clone());
+    let duration_ms = (info.duration_seconds * 1000.0).round() as u64;
+    let interval_ms = (options.interval_secs * 1000.0).round() as u64;
+    let mut vtt = String::from("WEBVTT\n");
+    for i in 0..count {
+        let start = i as u64 * interval_ms;
+        let end = (start + interval_ms).min(duration_ms);
+        let (x, y) = ((i % columns) * thumb_w, (i / columns) * thumb_h);
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{image_ref}#xywh={x},{y},{thumb_w},{thumb_h}\n",
+            format_timestamp(start),
+            format_timestamp(end)
+        ));
+    }
+    std::fs::write(&options.output_vtt, vtt).map_err(|e| format!("Failed to write thumbnails vtt: {e}"))?;
+
+    Ok(SpriteSheet {
+        output_image: options.output_image,
+        output_vtt: options.output_vtt,
+        thumbnails: count,
+    })
+}
+
+/// `count` separate jpegs spread evenly over the video for a gallery, returned in time order. Each
+/// frame sits in the middle of its slice, so black intros and end cards are skipped.
+#[tauri::command]
+async fn generate_thumbnails(window: tauri::Window, options: ThumbnailOptions) -> Result<Vec<String>, String> {
+    if !(1..=MAX_THUMBNAILS).contains(&options.count) {
+        return Err(format!("Thumbnail count must be between 1 and {MAX_THUMBNAILS}, got {}", options.count));
+    }
+    ensure_safe_paths(&window, &[&options.input_path], &[])?;
+    let info = parse_media_info(probe_json(options.input_path.clone()).await?)?;
+    if !info.has_video || info.is_image {
+        return Err("Thumbnails need a video source".to_string());
+    }
+    let width = options.width.unwrap_or(THUMBNAIL_WIDTH);
+    if !(16..=3840).contains(&width) {
+        return Err(format!("Thumbnail width must be between 16 and 3840, got {width}"));
+    }
+
+    let input = PathBuf::from(&options.input_path);
+    let dir = match options.output_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => input.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+    };
+    ensure_safe_paths(&window, &[], &[&dir.to_string_lossy()])?;
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    let slice_ms = info.duration_seconds * 1000.0 / options.count as f64;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut outputs = Vec::new();
+        for i in 0..options.count {
+            let at_ms = (slice_ms * (i as f64 + 0.5)).round() as u64;
+            let output = avoid_collision(dir.join(format!("{stem}_thumb_{:02}.jpg", i + 1)))
+                .to_string_lossy()
+                .to_string();
+            // input seek lands on the nearest keyframe cheaply, close enough for a gallery
+            let args: Vec<String> = vec![
+                "-y".to_string(),
+                "-ss".to_string(),
+                format_timestamp(at_ms),
+                "-i".to_string(),
+                options.input_path.clone(),
+                "-frames:v".to_string(),
+                "1".to_string(),
+                "-vf".to_string(),
+                format!("scale={width}:-2"),
+                "-q:v".to_string(),
+                "2".to_string(),
+                output.clone(),
+            ];
+            run_ffmpeg(&window, &args)?;
+            outputs.push(output);
+        }
+        Ok::<_, String>(outputs)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))?
+}
+
+const MAX_THUMBNAILS: u32 = 100;
+const THUMBNAIL_WIDTH: u32 = 320;
+
+// a few hundred tiles is already a multi-MB download
+const MAX_SPRITE_THUMBNAILS: u32 = 600;
+// jpeg and webp top out at 65535px a side
+const MAX_SPRITE_SIDE: u64 = 65535;
+
+/// Small looping GIF or animated WebP starting at `at_ms`, for hover thumbnails. Size and length
+/// are capped since these get loaded by the dozen in a grid.
+#[tauri::command]
+async fn make_hover_preview(
+    window: tauri::Window,
+    input_path: String,
+    at_ms: u64,
+    format: Option<String>,
+    width: Option<u32>,
+    duration_secs: Option<f64>,
+    output_path: Option<String>,
+) -> Result<String, String> {
+    ensure_safe_paths(&window, &[&input_path], &[])?;
+    let info = parse_media_info(probe_json(input_path.clone()).await?)?;
+    if !info.has_video || info.is_image {
+        return Err("Hover previews need a video source".to_string());
+    }
+    let format = format.unwrap_or_else(|| "gif".to_string());
+    if !matches!(format.as_str(), "gif" | "webp") {
+        return Err(format!("Hover previews can be gif or webp, not {format}"));
+    }
+
+    let target_width = width.unwrap_or(HOVER_PREVIEW_WIDTH).min(HOVER_PREVIEW_MAX_WIDTH);
+    let (width, height) = match (info.width, info.height) {
+        (Some(w), Some(h)) if w > target_width => {
+            let scaled = (h as f64 * target_width as f64 / w as f64).round() as u32;
+            (target_width - target_width % 2, scaled + scaled % 2)
+        }
+        (Some(w), Some(h)) => (w, h),
+        _ => return Err("Source has no frame size".to_string()),
+    };
+    let duration = duration_secs.unwrap_or(HOVER_PREVIEW_SECS).clamp(0.5, HOVER_PREVIEW_MAX_SECS);
+    let source_ms = (info.duration_seconds * 1000.0).round() as u64;
+    let start_ms = at_ms.min(source_ms.saturating_sub(500));
+    let end_ms = (start_ms + (duration * 1000.0).round() as u64).min(source_ms);
+
+    let output_path = output_path.unwrap_or_else(|| {
+        let input = PathBuf::from(&input_path);
+        let stem = input
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "output".to_string());
+        avoid_collision(input.with_file_name(format!("{stem}_preview.{format}")))
+            .to_string_lossy()
+            .to_string()
+    });
+    ensure_safe_paths(&window, &[], &[&output_path])?;
+
+    let args = if format == "gif" {
+        // same palette pipeline as a full gif export, just smaller
+        let options = ConversionOptions {
+            input_path,
+            output_path: output_path.clone(),
+            start_ms,
+            end_ms,
+            width: Some(width),
+            height: Some(height),
+            fps: Some(HOVER_PREVIEW_FPS),
+            format: Some("gif".to_string()),
+            gif_max_colors: Some(128),
+            ..Default::default()
+        };
+        build_ffmpeg_args(&options).map_err(|e| format!("Argument error: {}", e))?
+    } else {
+        vec![
+            "-y".to_string(),
+            "-ss".to_string(),
+            format_timestamp(start_ms),
+            "-i".to_string(),
+            input_path,
+            "-t".to_string(),
+            format_timestamp(end_ms - start_ms),
+            "-vf".to_string(),
+            format!("fps={HOVER_PREVIEW_FPS},scale={width}:{height}"),
+            "-c:v".to_string(),
+            "libwebp".to_string(),
+            "-loop".to_string(),
+            "0".to_string(),
+            "-q:v".to_string(),
+            "60".to_string(),
+            "-an".to_string(),
+            output_path.clone(),
+        ]
+    };
+
+    tauri::async_runtime::spawn_blocking(move || run_ffmpeg(&window, &args))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+    Ok(output_path)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BenchmarkCandidate {
+    format: String,
+    video_codec: String,
+    preset: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchmarkEntry {
+    format: String,
+    video_codec: String,
+    preset: Option<String>,
+    encode_seconds: f64,
+    size_bytes: u64,
+    // set when this candidate couldn't be encoded (missing encoder, bad preset), the rest still run
+    error: Option<String>,
+}
+
+const BENCHMARK_CLIP_SECS: u64 = 10;
+
+fn default_benchmark_candidates() -> Vec<BenchmarkCandidate> {
+    [("mp4", "libx264", Some("medium")), ("mp4", "libx265", None), ("webm", "libvpx-vp9", None)]
+        .into_iter()
+        .map(|(format, codec, preset)| BenchmarkCandidate {
+            format: format.to_string(),
+            video_codec: codec.to_string(),
+            preset: preset.map(|p| p.to_string()),
+        })
+        .collect()
+}
+
+/// Encodes the same short clip with each candidate, one after another, and reports time and size,
+/// smallest file first. Outputs go to the temp dir and are deleted afterwards.
+#[tauri::command]
+async fn codec_benchmark(
+    window: tauri::Window,
+    input_path: String,
+    candidates: Option<Vec<BenchmarkCandidate>>,
+    clip_secs: Option<u64>,
+) -> Result<Vec<BenchmarkEntry>, String> {
+    // the encodes themselves go to the work dir and are deleted again
+    ensure_safe_paths(&window, &[&input_path], &[])?;
+    let info = parse_media_info(probe_json(input_path.clone()).await?)?;
+    if !info.has_video || info.is_image {
+        return Err("Benchmarks need a video source".to_string());
+    }
+    let candidates = candidates.filter(|c| !c.is_empty()).unwrap_or_else(default_benchmark_candidates);
+
+    // take the clip from the middle, intros and credits are usually unrepresentative
+    let duration_ms = (info.duration_seconds * 1000.0).round() as u64;
+    let clip_ms = (clip_secs.unwrap_or(BENCHMARK_CLIP_SECS) * 1000).min(duration_ms);
+    let start_ms = (duration_ms - clip_ms) / 2;
+
+    // other conversions running at the same time would skew the timings
+    let _slot = acquire_conversion_slot(&window).await?;
+    let mut entries = tauri::async_runtime::spawn_blocking(move || {
+        let dir = work_dir(&window);
+        let mut entries = Vec::new();
+        for (i, candidate) in candidates.into_iter().enumerate() {
+            let output = dir.join(format!("xhmpeg-benchmark-{i}.{}", candidate.format));
+            let options = ConversionOptions {
+                input_path: input_path.clone(),
+                output_path: output.to_string_lossy().to_string(),
+                start_ms,
+                end_ms: start_ms + clip_ms,
+                format: Some(candidate.format.clone()),
+                video_codec: Some(candidate.video_codec.clone()),
+                preset: candidate.preset.clone(),
+                ..Default::default()
+            };
+            let started = std::time::Instant::now();
+            let outcome = match build_ffmpeg_args(&options) {
+                Ok(args) => match run_ffmpeg(&window, &args) {
+                    // a cancel is meant for the whole benchmark, not just this candidate
+                    Err(e) if e.code == ErrorCode::Cancelled => {
+                        let _ = std::fs::remove_file(&output);
+                        return Err(e.into());
+                    }
+                    result => result.map(|_| ()).map_err(String::from),
+                },
+                Err(e) => Err(format!("Argument error: {}", e)),
+            };
+            let encode_seconds = started.elapsed().as_secs_f64();
+            let size_bytes = outcome.and_then(|_| {
+                std::fs::metadata(&output).map(|m| m.len()).map_err(|e| format!("Benchmark output is missing: {e}"))
+            });
+            let _ = std::fs::remove_file(&output);
+
+            entries.push(BenchmarkEntry {
+                format: candidate.format,
+                video_codec: candidate.video_codec,
+                preset: candidate.preset,
+                encode_seconds,
+                size_bytes: *size_bytes.as_ref().unwrap_or(&0),
+                error: size_bytes.err(),
+            });
+        }
+        Ok::<_, String>(entries)
+    }).await.map_err(|e| format!("Task failed: {}", e))??;
+
+    // failed candidates go last
+    entries.sort_by_key(|e| (e.error.is_some(), e.size_bytes));
+    Ok(entries)
+}
+
+#[tauri::command]
+async fn make_visualizer_video(window: tauri::Window, options: VisualizerOptions) -> Result<String, String> {
+    ensure_safe_paths(&window, &[&options.input_audio], &[&options.output_path])?;
+    let output_path = options.output_path.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let args = build_visualizer_args(&options).map_err(|e| format!("Argument error: {}", e))?;
+        run_ffmpeg(&window, &args)
+    }).await.map_err(|e| format!("Task failed: {}", e))??;
+
+    Ok(output_path)
+}
+
+fn build_visualizer_args(options: &VisualizerOptions) -> Result<Vec<String>, String> {
+    let width = options.width.unwrap_or(1280);
+    let height = options.height.unwrap_or(720);
+    if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+        return Err(format!("Visualizer size must be even, got {width}x{height}"));
+    }
+
+    let background = options.background.as_deref().unwrap_or("black");
+    // the color ends up inside the filter graph, so keep it to plain names and hex values
+    if background.is_empty()
+        || !background.chars().all(|c| c.is_ascii_alphanumeric() || c == '#' || c == '@' || c == '.')
+    {
+        return Err(format!("Invalid background color: {background}"));
+    }
+
+    let visual = match options.mode.as_str() {
+        "spectrum" => format!("showspectrum=s={width}x{height}:slide=scroll:color=intensity"),
+        "waves" => format!("showwaves=s={width}x{height}:mode=cline:colors=white"),
+        other => return Err(format!("Unsupported visualizer mode: {other}")),
+    };
+    let graph = format!(
+        "[0:a]{visual},format=yuva420p[vis];color=c={background}:s={width}x{height}[bg];[bg][vis]overlay=shortest=1,format=yuv420p[v]"
+    );
+
+    let video_codec = video_codecs_for_format("mp4")[0];
+    let audio_codec = audio_codecs_for_format("mp4")[0];
+
+    Ok(vec![
+        "-y".to_string(),
+        "-i".to_string(),
+        options.input_audio.clone(),
+        "-filter_complex".to_string(),
+        graph,
+        "-map".to_string(),
+        "[v]".to_string(),
+        "-map".to_string(),
+        "0:a".to_string(),
+        "-c:v".to_string(),
+        video_codec.to_string(),
+        "-c:a".to_string(),
+        audio_codec.to_string(),
+        "-shortest".to_string(),
+        "-movflags".to_string(),
+        "+faststart".to_string(),
+        options.output_path.clone(),
+    ])
+}
+
+#[tauri::command]
+async fn detect_crop(window: tauri::Window, path: String) -> Result<CropRect, String> {
+    ensure_safe_paths(&window, &[&path], &[])?;
+    let info = parse_media_info(probe_json(path.clone()).await?)?;
+    if !info.has_video {
+        return Err("Crop detection needs a video stream".to_string());
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut votes: HashMap<CropRect, u32> = HashMap::new();
+        // sample across the whole file, the opening seconds are often a black intro
+        for fraction in CROP_SAMPLE_POINTS {
+            let at = format_timestamp((info.duration_seconds * fraction * 1000.0) as u64);
+            let output = Command::new(resolve_tool("ffmpeg"))
+                .args([
+                    "-hide_banner",
+                    "-ss",
+                    &at,
+                    "-i",
+                    &path,
+                    "-t",
+                    &format!("{CROP_SAMPLE_SECS}"),
+                    "-vf",
+                    "cropdetect=24:2:0",
+                    "-f",
+                    "null",
+                    "-",
+                ])
+                .output()
+                .map_err(|e| format!("Failed to run ffmpeg: {e}"))?;
+
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            // cropdetect settles over time, so the last suggestion of each sample is the one to trust
+            if let Some(rect) = stderr.lines().rev().find_map(parse_cropdetect_line) {
+                *votes.entry(rect).or_insert(0) += 1;
+            }
+        }
+
+        votes
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(rect, _)| rect)
+            .ok_or_else(|| "cropdetect produced no suggestion".to_string())
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn convert_to_dash(window: tauri::Window, options: DashOptions) -> Result<DashResult, String> {
+    ensure_safe_paths(&window, &[&options.input_path], &[&options.output_dir])?;
+    let info = parse_media_info(probe_json(options.input_path.clone()).await?)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let out_dir = PathBuf::from(&options.output_dir);
+        std::fs::create_dir_all(&out_dir).map_err(|e| format!("Failed to create output folder: {}", e))?;
+        let manifest = out_dir.join("manifest.mpd");
+
+        let args = build_dash_args(&options, &info, &manifest)?;
+        run_ffmpeg(&window, &args)?;
+
+        let mut segments: Vec<String> = std::fs::read_dir(&out_dir)
+            .map_err(|e| format!("Failed to list output folder: {}", e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("m4s"))
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        segments.sort();
+
+        Ok(DashResult {
+            manifest_path: manifest.to_string_lossy().to_string(),
+            segments,
+        })
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))?
+}
+
+const MAX_X264_CRF: u8 = 51;
+
+fn build_dash_args(options: &DashOptions, info: &MediaInfo, manifest: &std::path::Path) -> Result<Vec<String>, String> {
+    if !info.has_video {
+        return Err("DASH output needs a video stream".to_string());
+    }
+    if options.renditions.is_empty() {
+        return Err("Add at least one DASH rendition".to_string());
+    }
+    if let Some(r) = options
+        .renditions
+        .iter()
+        .find(|r| !r.width.is_multiple_of(2) || !r.height.is_multiple_of(2))
+    {
+        return Err(format!("Rendition size must be even, got {}x{}", r.width, r.height));
+    }
+    for r in &options.renditions {
+        match (r.video_bitrate_kbps, r.crf) {
+            (Some(_), Some(_)) | (None, None) => {
+                return Err(format!("Rendition {}x{} needs either a bitrate or a crf", r.width, r.height))
+            }
+            (_, Some(crf)) if crf > MAX_X264_CRF => {
+                return Err(format!("CRF must be between 0 and {MAX_X264_CRF}, got {crf}"))
+            }
+            _ => {}
+        }
+    }
+
+    let segment_secs = options.segment_secs.unwrap_or(4);
+    if segment_secs == 0 {
+        return Err("DASH segments must be at least 1s long".to_string());
+    }
+
+    // one decode, split into every representation
+    let count = options.renditions.len();
+    let mut graph = format!("[0:v]split={count}");
+    for i in 0..count {
+        graph.push_str(&format!("[s{i}]"));
+    }
+    for (i, r) in options.renditions.iter().enumerate() {
+        graph.push_str(&format!(";[s{i}]scale={}:{}[v{i}]", r.width, r.height));
+    }
+
+    let video_codec = video_codecs_for_format("mp4")[0];
+    let mut args: Vec<String> = vec![
+        "-y".to_string(),
+        "-i".to_string(),
+        options.input_path.clone(),
+        "-filter_complex".to_string(),
+        graph,
+    ];
+    for (i, r) in options.renditions.iter().enumerate() {
+        args.push("-map".to_string());
+        args.push(format!("[v{i}]"));
+        args.push(format!("-c:v:{i}"));
+        args.push(video_codec.to_string());
+        // players switch renditions at segment boundaries, so every one needs a keyframe at the
+        // same times; scene cuts would otherwise move them around per encode
+        args.push(format!("-force_key_frames:v:{i}"));
+        args.push(format!("expr:gte(t,n_forced*{segment_secs})"));
+        args.push(format!("-sc_threshold:v:{i}"));
+        args.push("0".to_string());
+        if let Some(crf) = r.crf {
+            args.push(format!("-crf:v:{i}"));
+            args.push(crf.to_string());
+            if let Some(max) = r.max_bitrate_kbps {
+                args.push(format!("-maxrate:v:{i}"));
+                args.push(format!("{max}k"));
+                args.push(format!("-bufsize:v:{i}"));
+                args.push(format!("{}k", max * 2));
+            }
+        } else if let Some(vb) = r.video_bitrate_kbps {
+            args.push(format!("-b:v:{i}"));
+            args.push(format!("{vb}k"));
+        }
+    }
+    args.push("-pix_fmt".to_string());
+    args.push("yuv420p".to_string());
+
+    let mut adaptation_sets = "id=0,streams=v".to_string();
+    if info.has_audio {
+        args.push("-map".to_string());
+        args.push("0:a:0".to_string());
+        args.push("-c:a".to_string());
+        args.push(audio_codecs_for_format("mp4")[0].to_string());
+        args.push("-b:a".to_string());
+        args.push(format!("{}k", options.audio_bitrate_kbps.unwrap_or(128)));
+        adaptation_sets.push_str(" id=1,streams=a");
+    }
+
+    args.extend([
+        "-f".to_string(),
+        "dash".to_string(),
+        "-seg_duration".to_string(),
+        segment_secs.to_string(),
+        "-use_template".to_string(),
+        "1".to_string(),
+        "-use_timeline".to_string(),
+        "1".to_string(),
+        "-adaptation_sets".to_string(),
+        adaptation_sets,
+        manifest.to_string_lossy().to_string(),
+    ]);
+    Ok(args)
+}
+
+#[tauri::command]
+async fn mux_tracks(window: tauri::Window, options: MuxOptions) -> Result<String, String> {
+    let inputs: Vec<&str> = std::iter::once(options.video_path.as_str())
+        .chain(options.audio_paths.iter().chain(&options.subtitle_paths).map(|t| t.path.as_str()))
+        .collect();
+    ensure_safe_paths(&window, &inputs, &[&options.output_path])?;
+    let output_path = options.output_path.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let args = build_mux_args(&options).map_err(|e| format!("Argument error: {}", e))?;
+        run_ffmpeg(&window, &args)
+    }).await.map_err(|e| format!("Task failed: {}", e))??;
+
+    Ok(output_path)
+}
+
+// Language tags and dispositions for one track type. Once any track is marked default the rest
+// get an explicit 0, otherwise the muxer keeps flagging the first one as default too.
+fn build_track_flags(kind: &str, tracks: &[MuxTrack]) -> Result<Vec<String>, String> {
+    if tracks.iter().filter(|t| t.default).count() > 1 {
+        let name = if kind == "a" { "audio" } else { "subtitle" };
+        return Err(format!("Only one {name} track can be the default"));
+    }
+    let any_default = tracks.iter().any(|t| t.default);
+
+    let mut args = Vec::new();
+    for (i, track) in tracks.iter().enumerate() {
+        if let Some(ref lang) = track.language {
+            if !(2..=3).contains(&lang.len()) || !lang.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(format!("Language must be an ISO 639 code like eng or en, got {lang}"));
+            }
+            args.push(format!("-metadata:s:{kind}:{i}"));
+            args.push(format!("language={}", lang.to_ascii_lowercase()));
+        }
+        let disposition = match (track.default, track.forced) {
+            (true, true) => "default+forced",
+            (true, false) => "default",
+            (false, true) => "forced",
+            (false, false) if any_default => "0",
+            (false, false) => continue,
+        };
+        args.push(format!("-disposition:{kind}:{i}"));
+        args.push(disposition.to_string());
+    }
+    Ok(args)
+}
+
+fn build_mux_args(options: &MuxOptions) -> Result<Vec<String>, String> {
+    if options.audio_paths.is_empty() && options.subtitle_paths.is_empty() {
+        return Err("Add at least one audio or subtitle track to mux".to_string());
+    }
+
+    let mut args: Vec<String> = vec!["-y".to_string(), "-i".to_string(), options.video_path.clone()];
+    for track in options.audio_paths.iter().chain(&options.subtitle_paths) {
+        args.push("-i".to_string());
+        args.push(track.path.clone());
+    }
+
+    args.push("-map".to_string());
+    args.push("0:v:0".to_string());
+    let audio_count = options.audio_paths.len();
+    for i in 0..audio_count {
+        args.push("-map".to_string());
+        args.push(format!("{}:a:0", i + 1));
+    }
+    for i in 0..options.subtitle_paths.len() {
+        args.push("-map".to_string());
+        args.push(format!("{}:s:0", audio_count + i + 1));
+    }
+
+    // matroska takes nearly any codec, so every track can be copied untouched
+    args.push("-c".to_string());
+    args.push("copy".to_string());
+
+    for (kind, tracks) in [("a", &options.audio_paths), ("s", &options.subtitle_paths)] {
+        args.extend(build_track_flags(kind, tracks)?);
+    }
+
+    args.push("-f".to_string());
+    args.push("matroska".to_string());
+    args.push(options.output_path.clone());
+    Ok(args)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepairResult {
+    issues: Vec<String>,
+    // None when nothing needed fixing
+    output_path: Option<String>,
+}
+
+/// Lossless fix for files that won't seek: remuxes with moov moved up front (mp4/mov) or with
+/// a rebuilt index and regenerated timestamps (everything else). The original is left alone.
+#[tauri::command]
+async fn repair_container(window: tauri::Window, path: String) -> Result<RepairResult, String> {
+    ensure_safe_paths(&window, &[&path], &[])?;
+    let value = probe_json(path.clone()).await?;
+    let format_name = value
+        .get("format")
+        .and_then(|f| f.get("format_name"))
+        .and_then(|n| n.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let has_duration = value
+        .get("format")
+        .and_then(|f| f.get("duration"))
+        .and_then(|d| d.as_str())
+        .and_then(|d| d.parse::<f64>().ok())
+        .is_some_and(|d| d > 0.0);
+    let is_iso = format_name.split(',').any(|n| n == "mp4" || n == "mov");
+
+    let mut issues = Vec::new();
+    if is_iso {
+        match source_has_faststart(&path) {
+            Some(true) => {}
+            Some(false) => issues.push("moov atom is at the end of the file".to_string()),
+            // remuxing can't bring back an index that was never written
+            None => return Err("No moov atom found, the file is truncated and can't be repaired by remuxing".to_string()),
+        }
+    }
+    if !has_duration {
+        issues.push("container has no duration, the index is likely broken".to_string());
+    }
+    if issues.is_empty() {
+        return Ok(RepairResult { issues, output_path: None });
+    }
+
+    let input = PathBuf::from(&path);
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    let file = match input.extension() {
+        Some(ext) => format!("{stem}_fixed.{}", ext.to_string_lossy()),
+        None => format!("{stem}_fixed"),
+    };
+    let output = avoid_collision(input.with_file_name(file)).to_string_lossy().to_string();
+    ensure_safe_paths(&window, &[], &[&output])?;
+
+    let mut args: Vec<String> = ["-y", "-fflags", "+genpts", "-i", &path, "-map", "0", "-c", "copy"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    if is_iso {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output.clone());
+
+    tauri::async_runtime::spawn_blocking(move || run_ffmpeg(&window, &args))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+
+    Ok(RepairResult {
+        issues,
+        output_path: Some(output),
+    })
+}
+
+/// Turns the video by rewriting only the mp4/mov display matrix, with -c copy. Instant and lossless,
+/// but it's up to the player: browsers, VLC, QuickTime and phone galleries honor it, while some
+/// editors, older TVs and a few upload sites show the stored orientation. Re-encode with the rotate
+/// option when the orientation has to be baked into the pixels.
+#[tauri::command]
+async fn rotate_metadata_only(
+    window: tauri::Window,
+    path: String,
+    degrees: u32,
+    output_path: Option<String>,
+) -> Result<String, String> {
+    if !matches!(degrees, 90 | 180 | 270) {
+        return Err(format!("Rotation must be 90, 180 or 270 degrees, got {degrees}"));
+    }
+    let input = PathBuf::from(&path);
+    let ext = input.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    if !matches!(ext.as_str(), "mp4" | "mov" | "m4v") {
+        return Err(format!("Rotation metadata only exists in mp4/mov, not .{ext}"));
+    }
+    ensure_safe_paths(&window, &[&path], &[])?;
+    let info = parse_media_info(probe_json(path.clone()).await?)?;
+    if !info.has_video || info.is_image {
+        return Err("Rotation needs a video stream".to_string());
+    }
+    let rotation = (info.rotation.unwrap_or(0) + degrees) % 360;
+
+    let output = output_path.unwrap_or_else(|| {
+        let stem = input
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "output".to_string());
+        avoid_collision(input.with_file_name(format!("{stem}_rotated.{ext}")))
+            .to_string_lossy()
+            .to_string()
+    });
+    ensure_safe_paths(&window, &[], &[&output])?;
+    let version = window.state::<FfmpegCapabilities>().version();
+    let mut args: Vec<String> = vec!["-y".to_string()];
+    // 6.1+ takes -display_rotation (counter-clockwise, replaces the file's matrix); older builds
+    // still turn the legacy rotate tag into the matrix when muxing
+    if version.is_none_or(|v| v >= DISPLAY_ROTATION_VERSION) {
+        args.push("-display_rotation:v:0".to_string());
+        args.push(((360 - rotation) % 360).to_string());
+        args.extend(["-i", &path, "-map", "0", "-c", "copy"].map(String::from));
+    } else {
+        args.extend(["-i", &path, "-map", "0", "-c", "copy"].map(String::from));
+        args.push("-metadata:s:v:0".to_string());
+        args.push(format!("rotate={rotation}"));
+    }
+    args.push(output.clone());
+
+    tauri::async_runtime::spawn_blocking(move || run_ffmpeg(&window, &args))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+    Ok(output)
+}
+
+const DISPLAY_ROTATION_VERSION: (u32, u32) = (6, 1);
+
+/// Writes every channel of the first audio stream to its own 24-bit wav, named by channel label
+/// (clip_FL.wav, clip_FR.wav, ...) in output_dir or next to the source.
+#[tauri::command]
+async fn split_channels(window: tauri::Window, path: String, output_dir: Option<String>) -> Result<Vec<String>, String> {
+    ensure_safe_paths(&window, &[&path], &[])?;
+    let json = probe_json(path.clone()).await?;
+    let stream = json["streams"]
+        .as_array()
+        .and_then(|streams| streams.iter().find(|s| s["codec_type"] == "audio"))
+        .ok_or_else(|| "The source has no audio stream".to_string())?;
+    let channels = stream["channels"].as_u64().unwrap_or(0) as usize;
+    if channels < 2 {
+        return Err(format!("Nothing to split, the audio has {channels} channel(s)"));
+    }
+    let layout = stream["channel_layout"].as_str().unwrap_or_default().to_string();
+
+    let input = PathBuf::from(&path);
+    let dir = match output_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => input.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+    };
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    ensure_safe_paths(&window, &[], &[&dir.to_string_lossy()])?;
+
+    let mut args: Vec<String> = vec!["-y".to_string(), "-i".to_string(), path.clone()];
+    let labels: Vec<String> = match channel_labels(&layout) {
+        // channelsplit names its pads after the layout's channels
+        Some(labels) if labels.len() == channels => {
+            let pads: String = labels.iter().map(|l| format!("[{l}]")).collect();
+            args.push("-filter_complex".to_string());
+            args.push(format!("[0:a:0]channelsplit=channel_layout={layout}{pads}"));
+            labels.iter().map(|l| l.to_string()).collect()
+        }
+        // layouts without names (or ones ffprobe reports as "unknown") get picked apart by index
+        _ => {
+            let graph: Vec<String> = (0..channels).map(|i| format!("[0:a:0]pan=mono|c0=c{i}[c{i}]")).collect();
+            args.push("-filter_complex".to_string());
+            args.push(graph.join(";"));
+            (0..channels).map(|i| format!("c{i}")).collect()
+        }
+    };
+    let mut outputs = Vec::new();
+    for label in &labels {
+        let output = avoid_collision(dir.join(format!("{stem}_{label}.wav"))).to_string_lossy().to_string();
+        args.extend(["-map".to_string(), format!("[{label}]"), "-c:a".to_string(), "pcm_s24le".to_string()]);
+        args.push(output.clone());
+        outputs.push(output);
+    }
+
+    tauri::async_runtime::spawn_blocking(move || run_ffmpeg(&window, &args))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+    Ok(outputs)
+}
+
+// ffmpeg's names for the layouts ffprobe usually reports, in channel order
+fn channel_labels(layout: &str) -> Option<&'static [&'static str]> {
+    Some(match layout {
+        "stereo" => &["FL", "FR"],
+        "2.1" => &["FL", "FR", "LFE"],
+        "3.0" => &["FL", "FR", "FC"],
+        "quad" => &["FL", "FR", "BL", "BR"],
+        "5.0" => &["FL", "FR", "FC", "BL", "BR"],
+        "5.0(side)" => &["FL", "FR", "FC", "SL", "SR"],
+        "5.1" => &["FL", "FR", "FC", "LFE", "BL", "BR"],
+        "5.1(side)" => &["FL", "FR", "FC", "LFE", "SL", "SR"],
+        "7.1" => &["FL", "FR", "FC", "LFE", "BL", "BR", "SL", "SR"],
+        _ => return None,
+    })
+}
+
+#[tauri::command]
+async fn analyze_audio_loudness(window: tauri::Window, path: String) -> Result<LoudnessInfo, String> {
+    ensure_safe_paths(&window, &[&path], &[])?;
+    measure_loudness(path, None).await
+}
+
+// range is (start_ms, end_ms) of the source, for measuring just the part that gets exported
+async fn measure_loudness(path: String, range: Option<(u64, u64)>) -> Result<LoudnessInfo, String> {
+    let output = tauri::async_runtime::spawn_blocking(move || {
+        let mut cmd = Command::new(resolve_tool("ffmpeg"));
+        cmd.args(["-hide_banner", "-nostats"]);
+        if let Some((start_ms, end_ms)) = range {
+            cmd.args(["-ss", &format_timestamp(start_ms), "-t", &format_timestamp(end_ms.saturating_sub(start_ms))]);
+        }
+        cmd.args(["-i", &path, "-vn", "-af", "ebur128=peak=true,volumedetect", "-f", "null", "-"])
+            .output()
+    })
+    .await
+    .map_err(|e| format!("Failed to join ffmpeg task: {e}"))?
+    .map_err(|e| format!("Failed to run ffmpeg: {e}"))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("ffmpeg error: {stderr}"));
+    }
+
+    let info = parse_loudness(&stderr);
+    if info.integrated_lufs.is_none() && info.mean_volume_db.is_none() {
+        return Err("No audio loudness found, does the file have an audio stream?".to_string());
+    }
+    Ok(info)
+}
+
+// ReplayGain 2 is relative to -18 LUFS; Opus R128 gain is a Q7.8 integer relative to -23 LUFS
+fn loudness_tags(info: &LoudnessInfo, format: &str, audio_codec: Option<&str>) -> Result<Vec<(String, String)>, String> {
+    let lufs = info
+        .integrated_lufs
+        .ok_or_else(|| "No loudness measured, does the range have audio?".to_string())?;
+    if format == "opus" || audio_codec == Some("libopus") {
+        let gain = ((-23.0 - lufs) * 256.0).round() as i32;
+        return Ok(vec![("R128_TRACK_GAIN".to_string(), gain.to_string())]);
+    }
+    let mut tags = vec![("replaygain_track_gain".to_string(), format!("{:.2} dB", -18.0 - lufs))];
+    if let Some(peak) = info.true_peak_dbfs {
+        tags.push(("replaygain_track_peak".to_string(), format!("{:.6}", 10f64.powf(peak / 20.0))));
+    }
+    Ok(tags)
+}
+
+fn parse_loudness(stderr: &str) -> LoudnessInfo {
+    let mut info = LoudnessInfo::default();
+
+    // ebur128 logs running values per frame too, only the final summary block is authoritative
+    if let Some((_, summary)) = stderr.rsplit_once("Summary:") {
+        for line in summary.lines() {
+            let line = line.trim();
+            if let Some(v) = line.strip_prefix("I:") {
+                info.integrated_lufs = parse_leading_number(v);
+            } else if let Some(v) = line.strip_prefix("LRA:") {
+                info.loudness_range_lu = parse_leading_number(v);
+            } else if let Some(v) = line.strip_prefix("Peak:") {
+                info.true_peak_dbfs = parse_leading_number(v);
+            }
+        }
+    }
+
+    for line in stderr.lines() {
+        if let Some((_, v)) = line.split_once("max_volume:") {
+            info.max_volume_db = parse_leading_number(v);
+        } else if let Some((_, v)) = line.split_once("mean_volume:") {
+            info.mean_volume_db = parse_leading_number(v);
+        }
+    }
+
+    info
+}
+
+fn parse_leading_number(text: &str) -> Option<f64> {
+    text.split_whitespace().next()?.parse::<f64>().ok()
+}
+
+#[derive(Debug, Serialize)]
+pub struct QualityScore {
+    vmaf: f64,
+    // the converted file had a different size and was scaled to the reference before scoring
+    scaled: bool,
+}
+
+/// Full-reference VMAF of `distorted` against `reference` (0-100, ~95+ is visually transparent).
+#[tauri::command]
+async fn compare_quality(window: tauri::Window, reference: String, distorted: String) -> Result<QualityScore, String> {
+    ensure_safe_paths(&window, &[&reference, &distorted], &[])?;
+    if window
+        .state::<FfmpegCapabilities>()
+        .filters()
+        .is_some_and(|f| !f.contains("libvmaf"))
+    {
+        return Err("Your ffmpeg build has no libvmaf, install one built with --enable-libvmaf".to_string());
+    }
+    let reference_info = parse_media_info(probe_json(reference.clone()).await?)?;
+    let distorted_info = parse_media_info(probe_json(distorted.clone()).await?)?;
+    let (Some(width), Some(height)) = (reference_info.width, reference_info.height) else {
+        return Err("Reference has no video stream".to_string());
+    };
+    if !distorted_info.has_video {
+        return Err("Converted file has no video stream".to_string());
+    }
+
+    // libvmaf needs both inputs at the same size, and the scores are calibrated at the reference size
+    let scaled = (distorted_info.width, distorted_info.height) != (Some(width), Some(height));
+    let distorted_chain = if scaled {
+        format!("scale={width}:{height}:flags=bicubic,setpts=PTS-STARTPTS")
+    } else {
+        "setpts=PTS-STARTPTS".to_string()
+    };
+    let graph = format!("[0:v]{distorted_chain}[d];[1:v]setpts=PTS-STARTPTS[r];[d][r]libvmaf");
+
+    let output = tauri::async_runtime::spawn_blocking(move || {
+        Command::new(resolve_tool("ffmpeg"))
+            .args([
+                "-hide_banner",
+                "-nostats",
+                "-i",
+                &distorted,
+                "-i",
+                &reference,
+                "-lavfi",
+                &graph,
+                "-f",
+                "null",
+                "-",
+            ])
+            .output()
+    })
+    .await
+    .map_err(|e| format!("Failed to join ffmpeg task: {e}"))?
+    .map_err(|e| format!("Failed to run ffmpeg: {e}"))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("ffmpeg error: {stderr}"));
+    }
+    let vmaf = parse_vmaf_score(&stderr).ok_or_else(|| "libvmaf didn't report a score".to_string())?;
+    Ok(QualityScore { vmaf, scaled })
+}
+
+// "[Parsed_libvmaf_4 @ 0x...] VMAF score: 93.412051"
+fn parse_vmaf_score(stderr: &str) -> Option<f64> {
+    stderr
+        .lines()
+        .rev()
+        .find_map(|line| line.split_once("VMAF score:").and_then(|(_, rest)| parse_leading_number(rest)))
+}
+
+#[tauri::command]
+async fn list_keyframes(window: tauri::Window, path: String, start_ms: u64, end_ms: u64) -> Result<Vec<f64>, String> {
+    ensure_safe_paths(&window, &[&path], &[])?;
+    if end_ms <= start_ms {
+        return Err("Keyframe range end must be after its start".to_string());
+    }
+    // decoding every frame header is slow on long files, so keep the scan to a bounded window
+    if end_ms - start_ms > MAX_KEYFRAME_WINDOW_MS {
+        return Err(format!(
+            "Keyframe range can be at most {} seconds",
+            MAX_KEYFRAME_WINDOW_MS / 1000
+        ));
+    }
+
+    let intervals = format!("{}%{}", format_timestamp(start_ms), format_timestamp(end_ms));
+    let output = tauri::async_runtime::spawn_blocking(move || {
+        Command::new(resolve_tool("ffprobe"))
+            .args([
+                "-v",
+                "error",
+                "-select_streams",
+                "v:0",
+                "-skip_frame",
+                "nokey",
+                "-read_intervals",
+                &intervals,
+                "-show_entries",
+                "frame=pts_time",
+                "-of",
+                "csv=p=0",
+                &path,
+            ])
+            .output()
+    })
+    .await
+    .map_err(|e| format!("Failed to join ffprobe task: {e}"))?
+    .map_err(|e| format!("Failed to run ffprobe: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffprobe error: {stderr}"));
+    }
+
+    let start = start_ms as f64 / 1000.0;
+    let end = end_ms as f64 / 1000.0;
+    // read_intervals seeks to the keyframe before start, so trim back to the requested range
+    let mut times: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse::<f64>().ok())
+        .filter(|t| (start..=end).contains(t))
+        .collect();
+    times.sort_by(|a, b| a.total_cmp(b));
+    times.dedup();
+    Ok(times)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Chapter {
+    start_ms: u64,
+    end_ms: u64,
+    title: Option<String>,
+}
+
+/// Writes the source's chapters as an ffmetadata file (for import_chapters), WebVTT or a cue sheet.
+#[tauri::command]
+async fn export_chapters(
+    window: tauri::Window,
+    path: String,
+    output_path: String,
+    format: String,
+) -> Result<Vec<Chapter>, String> {
+    if !matches!(format.as_str(), "ffmetadata" | "vtt" | "cue") {
+        return Err(format!("Unsupported chapter format: {format} (use ffmetadata, vtt or cue)"));
+    }
+    ensure_safe_paths(&window, &[&path], &[&output_path])?;
+    let source = path.clone();
+    let output = tauri::async_runtime::spawn_blocking(move || {
+        Command::new(resolve_tool("ffprobe"))
+            .args(["-v", "error", "-print_format", "json", "-show_chapters"])
+            .arg(&source)
+            .output()
+    })
+    .await
+    .map_err(|e| format!("Failed to join ffprobe task: {e}"))?
+    .map_err(|e| format!("Failed to run ffprobe: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffprobe error: {stderr}"));
+    }
+    let json: Value = serde_json::from_slice(&output.stdout).map_err(|e| format!("Bad ffprobe output: {e}"))?;
+    let chapters = parse_chapters(&json);
+    if chapters.is_empty() {
+        return Err("The source has no chapters".to_string());
+    }
+
+    let contents = match format.as_str() {
+        "ffmetadata" => render_ffmetadata_chapters(&chapters),
+        "vtt" => render_vtt_chapters(&chapters),
+        _ => render_cue_chapters(&chapters, &path),
+    };
+    std::fs::write(&output_path, contents).map_err(|e| format!("Failed to write chapters: {e}"))?;
+    Ok(chapters)
+}
+
+fn parse_chapters(json: &Value) -> Vec<Chapter> {
+    let seconds_to_ms = |v: &Value| v.as_str().and_then(|s| s.parse::<f64>().ok()).map(|s| (s * 1000.0).round() as u64);
+    json["chapters"]
+        .as_array()
+        .map(|chapters| {
+            chapters
+                .iter()
+                .filter_map(|c| {
+                    Some(Chapter {
+                        start_ms: seconds_to_ms(&c["start_time"])?,
+                        end_ms: seconds_to_ms(&c["end_time"])?,
+                        title: c["tags"]["title"].as_str().map(str::to_string),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn render_ffmetadata_chapters(chapters: &[Chapter]) -> String {
+    // ffmetadata needs = ; # \ and newlines escaped with a backslash
+    let escape = |s: &str| {
+        s.chars().fold(String::new(), |mut out, c| {
+            if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+                out.push('\\');
+            }
+            out.push(c);
+            out
+        })
+    };
+    let mut out = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        out.push_str(&format!("[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\n", chapter.start_ms, chapter.end_ms));
+        if let Some(ref title) = chapter.title {
+            out.push_str(&format!("title={}\n", escape(title)));
+        }
+    }
+    out
+}
+
+fn render_vtt_chapters(chapters: &[Chapter]) -> String {
+    let mut out = String::from("WEBVTT\n");
+    for (i, chapter) in chapters.iter().enumerate() {
+        let title = chapter.title.clone().unwrap_or_else(|| format!("Chapter {}", i + 1));
+        out.push_str(&format!(
+            "\n{}\n{} --> {}\n{}\n",
+            i + 1,
+            format_timestamp(chapter.start_ms),
+            format_timestamp(chapter.end_ms),
+            title.replace("-->", "->")
+        ));
+    }
+    out
+}
+
+fn render_cue_chapters(chapters: &[Chapter], source: &str) -> String {
+    let path = std::path::Path::new(source);
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let file_type = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("mp3") => "MP3",
+        _ => "WAVE",
+    };
+    let mut out = format!("FILE \"{}\" {file_type}\n", file_name.replace('"', "'"));
+    for (i, chapter) in chapters.iter().enumerate() {
+        // cue positions are mm:ss:ff with 75 frames a second
+        let frames = chapter.start_ms * 75 / 1000;
+        out.push_str(&format!("  TRACK {:02} AUDIO\n", i + 1));
+        if let Some(ref title) = chapter.title {
+            out.push_str(&format!("    TITLE \"{}\"\n", title.replace('"', "'")));
+        }
+        out.push_str(&format!(
+            "    INDEX 01 {:02}:{:02}:{:02}\n",
+            frames / (75 * 60),
+            (frames / 75) % 60,
+            frames % 75
+        ));
+    }
+    out
+}
+
+const MAX_KEYFRAME_WINDOW_MS: u64 = 10 * 60 * 1000;
+
+const CROP_SAMPLE_POINTS: [f64; 5] = [0.1, 0.3, 0.5, 0.7, 0.9];
+const CROP_SAMPLE_SECS: u32 = 2;
+
+fn parse_cropdetect_line(line: &str) -> Option<CropRect> {
+    let (_, rest) = line.split_once("crop=")?;
+    let spec = rest.split_whitespace().next()?;
+    let mut parts = spec.split(':').map(|p| p.parse::<u32>().ok());
+    Some(CropRect {
+        width: parts.next()??,
+        height: parts.next()??,
+        x: parts.next()??,
+        y: parts.next()??,
+    })
+}
+
+#[derive(Default)]
+struct FfmpegCapabilities {
+    version: OnceLock<Option<(u32, u32)>>,
+    encoders: OnceLock<Option<HashSet<String>>>,
+    decoders: OnceLock<Option<HashSet<String>>>,
+    muxers: OnceLock<Option<HashSet<String>>>,
+    filters: OnceLock<Option<HashSet<String>>>,
+    hwaccels: OnceLock<Option<HashSet<String>>>,
+}
+
+// Each list is parsed from the bundled ffmpeg once and reused. None means ffmpeg couldn't be
+// queried, in which case the checks are left to ffmpeg itself.
+impl FfmpegCapabilities {
+    /// Major/minor release. None for git snapshots ("N-12345-g...") or when ffmpeg can't be run;
+    /// callers treat that as "recent enough".
+    fn version(&self) -> Option<(u32, u32)> {
+        *self
+            .version
+            .get_or_init(|| query_ffmpeg("-version").and_then(|t| parse_ffmpeg_version(&t)))
+    }
+
+    fn encoders(&self) -> Option<&HashSet<String>> {
+        self.encoders
+            .get_or_init(|| query_ffmpeg("-encoders").map(|t| parse_codec_list(&t)))
+            .as_ref()
+    }
+
+    fn decoders(&self) -> Option<&HashSet<String>> {
+        self.decoders
+            .get_or_init(|| query_ffmpeg("-decoders").map(|t| parse_codec_list(&t)))
+            .as_ref()
+    }
+
+    fn muxers(&self) -> Option<&HashSet<String>> {
+        self.muxers
+            .get_or_init(|| query_ffmpeg("-formats").map(|t| parse_muxer_list(&t)))
+            .as_ref()
+    }
+
+    fn filters(&self) -> Option<&HashSet<String>> {
+        self.filters
+            .get_or_init(|| query_ffmpeg("-filters").map(|t| parse_filter_list(&t)))
+            .as_ref()
+    }
+
+    fn hwaccels(&self) -> Option<&HashSet<String>> {
+        self.hwaccels
+            .get_or_init(|| query_ffmpeg("-hwaccels").map(|t| parse_hwaccel_list(&t)))
+            .as_ref()
+    }
+}
+
+fn parse_ffmpeg_version(text: &str) -> Option<(u32, u32)> {
+    let version = text
+        .lines()
+        .next()?
+        .strip_prefix("ffmpeg version ")?
+        .split_whitespace()
+        .next()?
+        .trim_start_matches('n');
+    let mut parts = version.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().and_then(|m| m.parse().ok()).unwrap_or(0);
+    Some((major, minor))
+}
+
+// Opus could not be muxed into mp4 at all before ffmpeg 4.0.
+const MIN_OPUS_MP4_VERSION: (u32, u32) = (4, 0);
+
+fn ensure_version_supports(args: &[String], version: (u32, u32)) -> Result<(), String> {
+    let opus = args.windows(2).any(|p| p[0] == "-c:a" && p[1] == "libopus");
+    let mp4_out = args
+        .last()
+        .is_some_and(|out| out.to_ascii_lowercase().ends_with(".mp4"));
+    if opus && mp4_out && version < MIN_OPUS_MP4_VERSION {
+        return Err(format!(
+            "Opus in mp4 needs ffmpeg {}.{} or newer, this build is {}.{}",
+            MIN_OPUS_MP4_VERSION.0, MIN_OPUS_MP4_VERSION.1, version.0, version.1
+        ));
+    }
+    Ok(())
+}
+
+fn query_ffmpeg(flag: &str) -> Option<String> {
+    let output = Command::new(resolve_tool("ffmpeg"))
+        .args(["-hide_banner", flag])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Filter rows look like " TSC abench  A->A  Benchmark part of a filtergraph." with no separator
+// line, so recognise them by the io column instead.
+fn parse_filter_list(text: &str) -> HashSet<String> {
+    text.lines()
+        .filter_map(|line| {
+            let mut cols = line.split_whitespace();
+            let _flags = cols.next()?;
+            let name = cols.next()?;
+            cols.next()?.contains("->").then(|| name.to_string())
+        })
+        .collect()
+}
+
+// "Hardware acceleration methods:" followed by one bare name per line.
+fn parse_hwaccel_list(text: &str) -> HashSet<String> {
+    text.lines()
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect()
+}
+
+fn ensure_hwaccel_available(args: &[String], available: &HashSet<String>) -> Result<(), String> {
+    for pair in args.windows(2) {
+        if pair[0] == "-hwaccel" && pair[1] != "auto" && !available.contains(&pair[1]) {
+            return Err(format!("Hardware decoding via {} is not available in your ffmpeg build", pair[1]));
+        }
+    }
+    Ok(())
+}
+
+// Filters that are optional at ffmpeg build time (fontconfig/freetype, libvmaf, ...).
+const OPTIONAL_FILTERS: [&str; 3] = ["drawtext", "sofalizer", "zscale"];
+
+fn ensure_filters_available(args: &[String], available: &HashSet<String>) -> Result<(), String> {
+    for pair in args.windows(2) {
+        if !matches!(pair[0].as_str(), "-vf" | "-af" | "-filter_complex") {
+            continue;
+        }
+        if let Some(missing) = OPTIONAL_FILTERS
+            .iter()
+            .find(|f| pair[1].contains(*f) && !available.contains(**f))
+        {
+            return Err(format!("The {missing} filter is not available in your ffmpeg build"));
+        }
+    }
+    Ok(())
+}
+
+// The listing is a legend, a "------" separator, then one " V....D name  description" row per codec.
+// Some builds print no separator, so fall back to rows whose first column looks like the flags.
+fn parse_codec_list(text: &str) -> HashSet<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let rows: Vec<&str> = match lines.iter().position(|line| line.trim_start().starts_with("---")) {
+        Some(sep) => lines[sep + 1..].to_vec(),
+        None => lines
+            .into_iter()
+            .filter(|line| {
+                line.split_whitespace()
+                    .next()
+                    .is_some_and(|flags| flags.len() == 6 && flags.chars().all(|c| "VASFXBDIL.".contains(c)))
+            })
+            .collect(),
+    };
+    rows.into_iter()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+// -formats rows are " DE matroska,webm  Matroska / WebM"; the flags column drops to a single
+// letter for demux- or mux-only formats, and newer builds add a 'd' for devices.
+fn parse_muxer_list(text: &str) -> HashSet<String> {
+    text.lines()
+        .skip_while(|line| !line.trim_start().starts_with("--"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut cols = line.split_whitespace();
+            let flags = cols.next()?;
+            let names = cols.next()?;
+            let is_flags = flags.chars().all(|c| "DEd.".contains(c));
+            (is_flags && flags.contains('E')).then_some(names)
+        })
+        .flat_map(|names| names.split(',').map(|n| n.to_string()))
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapabilityReport {
+    version: Option<String>,
+    encoders: Vec<String>,
+    decoders: Vec<String>,
+    muxers: Vec<String>,
+    filters: Vec<String>,
+}
+
+/// Everything the bundled ffmpeg can do, sorted, for the diagnostics screen and for graying out
+/// options. Lists are empty when ffmpeg couldn't be queried.
+#[tauri::command]
+async fn get_ffmpeg_capabilities(window: tauri::Window) -> Result<CapabilityReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let capabilities = window.state::<FfmpegCapabilities>();
+        let sorted = |set: Option<&HashSet<String>>| {
+            let mut list: Vec<String> = set.map(|s| s.iter().cloned().collect()).unwrap_or_default();
+            list.sort();
+            list
+        };
+        CapabilityReport {
+            version: capabilities.version().map(|(major, minor)| format!("{major}.{minor}")),
+            encoders: sorted(capabilities.encoders()),
+            decoders: sorted(capabilities.decoders()),
+            muxers: sorted(capabilities.muxers()),
+            filters: sorted(capabilities.filters()),
+        }
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))
+}
+
+fn ensure_encoders_available(args: &[String], available: &HashSet<String>) -> Result<(), String> {
+    for pair in args.windows(2) {
+        let flag = pair[0].as_str();
+        let is_codec_flag = flag == "-c" || flag.starts_with("-c:");
+        let codec = pair[1].as_str();
+        if is_codec_flag && codec != "copy" && !available.contains(codec) {
+            return Err(format!("Codec {codec} is not available in your ffmpeg build"));
+        }
+    }
+    Ok(())
+}
+
+fn video_codecs_for_format(fmt: &str) -> Vec<&'static str> {
+    match fmt {
+        "mp4" => vec!["libx264", "libx265"],
+        "mov" => vec!["libx264", "libx265", "prores_ks", "dnxhd", "mjpeg"],
+        "mkv" => vec!["libx264", "libx265", "libvpx-vp9", "prores_ks", "dnxhd", "mjpeg"],
+        "webm" => vec!["libvpx-vp9"],
+        "avi" => vec!["libx264", "mjpeg"],
+        "flv" => vec!["libx264"],
+        "gif" => vec!["gif"],
+        _ => vec![],
+    }
+}
+
+fn audio_codecs_for_format(fmt: &str) -> Vec<&'static str> {
+    match fmt {
+        "mp4" => vec!["aac", "libmp3lame", "libopus"],
+        "mov" => vec!["aac"],
+        "mkv" => vec!["aac", "libopus", "libvorbis", "libmp3lame", "flac"],
+        "webm" => vec!["libopus", "libvorbis"],
+        "avi" => vec!["libmp3lame"],
+        "flv" => vec!["aac"],
+        "gif" => vec![],
+        "mp3" => vec!["libmp3lame"],
+        "wav" => vec!["pcm_s16le"],
+        "flac" => vec!["flac"],
+        "m4a" | "aac" => vec!["aac"],
+        "ogg" => vec!["libvorbis"],
+        "opus" => vec!["libopus"],
+        "caf" => vec!["alac", "libopus", "pcm_s16le"],
+        // headerless PCM, the format name doubles as ffmpeg's muxer name
+        "s16le" | "raw" => vec!["pcm_s16le"],
+        "s24le" => vec!["pcm_s24le"],
+        "f32le" => vec!["pcm_f32le"],
+        _ => vec![],
+    }
+}
+
+// Highest bitrate each lossy encoder actually uses; above this they warn, clamp or bail out.
+fn max_audio_bitrate_kbps(codec: &str) -> Option<u64> {
+    match codec {
+        "aac" | "libfdk_aac" => Some(512),
+        "libopus" => Some(510),
+        "libvorbis" => Some(500),
+        "libmp3lame" => Some(320),
+        _ => None,
+    }
+}
+
+fn raw_pcm_muxer(fmt: &str) -> Option<&'static str> {
+    match fmt {
+        "s16le" | "raw" => Some("s16le"),
+        "s24le" => Some("s24le"),
+        "f32le" => Some("f32le"),
+        _ => None,
+    }
+}
+
+struct PlatformSpec {
+    name: &'static str,
+    width: u32,
+    height: u32,
+    fps: Option<f64>,
+    video_bitrate_kbps: u64,
+    audio_bitrate_kbps: u64,
+    format: &'static str,
+    video_codec: &'static str,
+    audio_codec: &'static str,
+}
+
+// Recommended upload specs per platform. Add a row here to support a new platform.
+const PLATFORM_PRESETS: &[PlatformSpec] = &[
+    PlatformSpec {
+        name: "youtube",
+        width: 1920,
+        height: 1080,
+        fps: None,
+        video_bitrate_kbps: 8000,
+        audio_bitrate_kbps: 192,
+        format: "mp4",
+        video_codec: "libx264",
+        audio_codec: "aac",
+    },
+    PlatformSpec {
+        name: "instagram",
+        width: 1080,
+        height: 1920,
+        fps: Some(30.0),
+        video_bitrate_kbps: 5000,
+        audio_bitrate_kbps: 128,
+        format: "mp4",
+        video_codec: "libx264",
+        audio_codec: "aac",
+    },
+    PlatformSpec {
+        name: "tiktok",
+        width: 1080,
+        height: 1920,
+        fps: Some(30.0),
+        video_bitrate_kbps: 6000,
+        audio_bitrate_kbps: 128,
+        format: "mp4",
+        video_codec: "libx264",
+        audio_codec: "aac",
+    },
+    PlatformSpec {
+        name: "twitter",
+        width: 1280,
+        height: 720,
+        fps: Some(30.0),
+        video_bitrate_kbps: 5000,
+        audio_bitrate_kbps: 128,
+        format: "mp4",
+        video_codec: "libx264",
+        audio_codec: "aac",
+    },
+];
+
+/// Draws the audio as the video track by turning audio_video_mode into a filter_complex, so the
+/// rest of the conversion (trim, codecs, bitrate) runs as usual.
+fn apply_audio_visual(options: &ConversionOptions) -> Result<ConversionOptions, String> {
+    if options.is_audio_only || options.copy_streams || options.still_image {
+        return Err("Waveform and spectrum modes need a video output".to_string());
+    }
+    if options.filter_complex.is_some() || options.speed.is_some() || options.reverse {
+        return Err("Waveform and spectrum modes can't be combined with filter_complex, speed or reverse".to_string());
+    }
+    let width = options.width.unwrap_or(1280);
+    let height = options.height.unwrap_or(720);
+    if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+        return Err(format!("Visualizer size must be even, got {width}x{height}"));
+    }
+    // same looks as make_visualizer_video
+    let visual = match options.audio_video_mode.as_deref() {
+        Some("spectrum") => format!("showspectrum=s={width}x{height}:slide=scroll:color=intensity"),
+        _ => format!("showwaves=s={width}x{height}:mode=cline:colors=white"),
+    };
+    let mut applied = options.clone();
+    // the audio is mapped straight from the input rather than through the graph, which lets
+    // build_audio_args put the usual -af chain on it
+    applied.filter_complex = Some(format!("[0:a:0]{visual},format=yuv420p[v]"));
+    applied.maps = Some(vec!["[v]".to_string(), "0:a:0".to_string()]);
+    Ok(applied)
+}
+
+/// Fills every field the user left empty with the platform's recommended value.
+fn apply_platform_preset(options: &ConversionOptions, name: &str) -> Result<ConversionOptions, String> {
+    let spec = PLATFORM_PRESETS
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| format!("Unknown platform preset: {name}"))?;
+
+    let mut resolved = options.clone();
+    if resolved.width.is_none() && resolved.height.is_none() && resolved.scale_percent.is_none() {
+        resolved.width = Some(spec.width);
+        resolved.height = Some(spec.height);
+    }
+    resolved.fps = resolved.fps.or(spec.fps);
+    resolved.video_bitrate_kbps = resolved.video_bitrate_kbps.or(Some(spec.video_bitrate_kbps));
+    resolved.audio_bitrate_kbps = resolved.audio_bitrate_kbps.or(Some(spec.audio_bitrate_kbps));
+    // the preset codecs only make sense in the preset container, so leave them alone if the user picked another
+    if resolved.format.as_deref().unwrap_or(spec.format) == spec.format {
+        resolved.format = Some(spec.format.to_string());
+        resolved.video_codec.get_or_insert_with(|| spec.video_codec.to_string());
+        resolved.audio_codec.get_or_insert_with(|| spec.audio_codec.to_string());
+    }
+    Ok(resolved)
+}
+
+// Anything that touches the picture, and so can't be done on copied video.
+fn changes_video(options: &ConversionOptions) -> bool {
+    options.width.is_some()
+        || options.height.is_some()
+        || options.scale_percent.is_some()
+        || options.fps.is_some()
+        || options.fps_standard.is_some()
+        || options.speed.is_some()
+        || options.crop.is_some()
+        || options.deinterlace.is_some()
+        || options.force_bt709_tags
+        || options.convert_to_bt709
+}
+
+/// Decides per stream whether the source can go into the target as is. Video that fits and isn't
+/// touched by any filter or encoder setting is copied; audio likewise. When only the audio fits the
+/// video is re-encoded and the audio with it, which costs next to nothing next to the video encode.
+fn apply_smart_copy(options: &mut ConversionOptions, probe: &Value) -> Result<(), String> {
+    if options.copy_streams || options.copy_video {
+        return Ok(());
+    }
+    let format = options.format.clone().unwrap_or_else(|| "mp4".to_string());
+    // outer None: no such stream; inner None: a codec the tables don't know (dts, av1, ...)
+    let codec_of = |kind: &str| -> Option<Option<&'static str>> {
+        let stream = probe["streams"]
+            .as_array()?
+            .iter()
+            .find(|s| s["codec_type"] == kind && s["disposition"]["attached_pic"] != 1)?;
+        Some(stream["codec_name"].as_str().and_then(encoder_for_codec))
+    };
+    let source_video = codec_of("video");
+    let source_audio = codec_of("audio");
+
+    let video_fits = match source_video {
+        None => true,
+        Some(None) => false,
+        Some(Some(codec)) => {
+            video_codecs_for_format(&format).contains(&codec)
+                && options.video_codec.as_deref().is_none_or(|c| c == codec)
+                && !changes_video(options)
+                && options.rotate.is_none()
+                && !(options.reverse || options.interpolate || options.timecode_overlay || options.still_image)
+                && !(options.seek_friendly || options.low_latency)
+                && options.video_bitrate_kbps.is_none()
+                && options.filter_complex.is_none()
+                // encoder settings mean the user wants this stream encoded
+                && options.preset.is_none()
+                && options.video_profile.is_none()
+                && options.x264_params.is_none()
+                && options.x265_params.is_none()
+                && options.hw_decode.is_none()
+        }
+    };
+    let audio_fits = match source_audio {
+        None => true,
+        Some(None) => false,
+        Some(Some(codec)) => {
+            audio_codecs_for_format(&format).contains(&codec)
+                && options.audio_codec.as_deref().is_none_or(|c| c == codec)
+                && options.audio_bitrate_kbps.is_none()
+                && options.sample_rate.is_none()
+                && options.channels.is_none()
+                && options.downmix.is_none()
+                && build_audio_filters(options)?.is_empty()
+        }
+    };
+
+    match (video_fits || options.is_audio_only, audio_fits) {
+        (true, true) => options.copy_streams = true,
+        (true, false) if !options.is_audio_only && source_video.is_some() => options.copy_video = true,
+        _ => {}
+    }
+    Ok(())
+}
+
+// ffprobe codec names to the encoder names the codec tables use
+fn encoder_for_codec(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "h264" => "libx264",
+        "hevc" => "libx265",
+        "vp9" => "libvpx-vp9",
+        "prores" => "prores_ks",
+        "dnxhd" => "dnxhd",
+        "mjpeg" => "mjpeg",
+        "gif" => "gif",
+        "aac" => "aac",
+        "mp3" => "libmp3lame",
+        "opus" => "libopus",
+        "vorbis" => "libvorbis",
+        "flac" => "flac",
+        "alac" => "alac",
+        "pcm_s16le" => "pcm_s16le",
+        "pcm_s24le" => "pcm_s24le",
+        _ => return None,
+    })
+}
+
+/// Reads the HDR10 side data off the first video frame and turns it into x265 params. Errors if
+/// the source isn't HDR10, since tagging SDR as HDR makes it look blown out.
+async fn probe_hdr_params(path: String) -> Result<String, String> {
+    let output = tauri::async_runtime::spawn_blocking(move || {
+        Command::new(resolve_tool("ffprobe"))
+            .args([
+                "-v",
+                "error",
+                "-select_streams",
+                "v:0",
+                "-read_intervals",
+                "%+#1",
+                "-show_frames",
+                "-show_entries",
+                "frame=side_data_list",
+                "-of",
+                "json",
+                &path,
+            ])
+            .output()
+    })
+    .await
+    .map_err(|e| format!("Failed to join ffprobe task: {e}"))?
+    .map_err(|e| format!("Failed to run ffprobe: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffprobe error: {stderr}"));
+    }
+    let value: Value = serde_json::from_slice(&output.stdout).map_err(|e| format!("Invalid ffprobe output: {e}"))?;
+    let side_data: Vec<&Value> = value
+        .get("frames")
+        .and_then(|f| f.as_array())
+        .and_then(|frames| frames.first())
+        .and_then(|frame| frame.get("side_data_list"))
+        .and_then(|l| l.as_array())
+        .map(|l| l.iter().collect())
+        .unwrap_or_default();
+    build_hdr_params(&side_data)
+}
+
+fn build_hdr_params(side_data: &[&Value]) -> Result<String, String> {
+    let find = |kind: &str| {
+        side_data
+            .iter()
+            .find(|d| d.get("side_data_type").and_then(|t| t.as_str()) == Some(kind))
+    };
+    // ffprobe prints these as fractions like "34000/50000"
+    let ratio = |entry: &Value, key: &str| {
+        entry.get(key).and_then(|v| v.as_str()).and_then(parse_frame_rate)
+    };
+
+    let mastering = find("Mastering display metadata")
+        .ok_or_else(|| "Source has no HDR10 mastering display metadata".to_string())?;
+    // x265 wants chromaticity in 0.00002 steps and luminance in 0.0001 cd/m2 steps
+    let point = |x: &str, y: &str| -> Option<String> {
+        let x = (ratio(mastering, x)? * 50000.0).round() as u64;
+        let y = (ratio(mastering, y)? * 50000.0).round() as u64;
+        Some(format!("({x},{y})"))
+    };
+    let luminance = |key: &str| ratio(mastering, key).map(|l| (l * 10000.0).round() as u64);
+    let master_display = (|| {
+        Some(format!(
+            "G{}B{}R{}WP{}L({},{})",
+            point("green_x", "green_y")?,
+            point("blue_x", "blue_y")?,
+            point("red_x", "red_y")?,
+            point("white_point_x", "white_point_y")?,
+            luminance("max_luminance")?,
+            luminance("min_luminance")?
+        ))
+    })()
+    .ok_or_else(|| "Mastering display metadata is incomplete".to_string())?;
+
+    let mut params = format!(
+        "hdr-opt=1:repeat-headers=1:colorprim=bt2020:transfer=smpte2084:colormatrix=bt2020nc:master-display={master_display}"
+    );
+    // content light level is optional in HDR10, plenty of masters leave it out
+    if let Some(cll) = find("Content light level metadata") {
+        let level = |key: &str| cll.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+        params.push_str(&format!(":max-cll={},{}", level("max_content"), level("max_average")));
+    }
+    Ok(params)
+}
+
+// Keyframes are forced by time rather than -g so the interval stays at a second whatever the fps.
+// ProRes, DNxHD and MJPEG are all-intra already, and GIF has no such thing.
+fn seek_friendly_args(video_codec: &str) -> Vec<String> {
+    let mut args: Vec<&str> = match video_codec {
+        "libx264" => vec!["-bf", "0", "-tune", "fastdecode"],
+        "libx265" => vec!["-bf", "0"],
+        // vp9 has no B-frames, but hidden alt-ref frames do the same damage to seeking
+        "libvpx-vp9" => vec!["-auto-alt-ref", "0"],
+        _ => return Vec::new(),
+    };
+    args.extend(["-force_key_frames", "expr:gte(t,n_forced*1)"]);
+    args.into_iter().map(String::from).collect()
+}
+
+// Both already drop B-frames and force 1s keyframes, so with seek_friendly the only thing left to
+// merge is the x264 tune.
+fn low_latency_args(video_codec: &str, seek_friendly: bool) -> Vec<String> {
+    let mut args: Vec<&str> = match video_codec {
+        // zerolatency also turns off rc-lookahead, mbtree and frame threading
+        "libx264" if seek_friendly => vec!["-tune", "zerolatency,fastdecode", "-bf", "0"],
+        "libx264" => vec!["-tune", "zerolatency", "-bf", "0"],
+        "libx265" => vec!["-tune", "zerolatency", "-bf", "0"],
+        "libvpx-vp9" => vec!["-deadline", "realtime", "-lag-in-frames", "0", "-auto-alt-ref", "0"],
+        _ => return Vec::new(),
+    };
+    args.extend(["-force_key_frames", "expr:gte(t,n_forced*1)"]);
+    args.into_iter().map(String::from).collect()
+}
+
+const HW_DECODERS: [&str; 7] = ["cuda", "qsv", "videotoolbox", "vaapi", "d3d11va", "dxva2", "auto"];
+
+// By default decoded frames are copied back to system memory, which is what the software filters
+// and encoders here need. Frames only stay on the GPU when nothing touches them in between and
+// the encoder belongs to the same API, e.g. cuda into *_nvenc.
+fn build_hw_decode_args(hw: &str, options: &ConversionOptions, format: &str) -> Result<Vec<String>, String> {
+    if !HW_DECODERS.contains(&hw) {
+        return Err(format!("Unsupported hardware decoder: {hw}"));
+    }
+    if options.is_audio_only || options.still_image {
+        return Err("Hardware decoding only applies to video sources".to_string());
+    }
+    let mut args = vec!["-hwaccel".to_string(), hw.to_string()];
+
+    let untouched = !changes_video(options)
+        && !options.timecode_overlay
+        && !options.interpolate
+        && !options.reverse
+        && options.filter_complex.is_none()
+        && options.cover_image.is_none()
+        && format != "gif";
+    let encoder_suffix = match hw {
+        "cuda" => Some("_nvenc"),
+        "qsv" => Some("_qsv"),
+        "videotoolbox" => Some("_videotoolbox"),
+        "vaapi" => Some("_vaapi"),
+        _ => None,
+    };
+    let same_api_encoder = encoder_suffix
+        .zip(options.video_codec.as_deref())
+        .is_some_and(|(suffix, codec)| codec.ends_with(suffix));
+    if untouched && same_api_encoder {
+        args.push("-hwaccel_output_format".to_string());
+        args.push(hw.to_string());
+    }
+    Ok(args)
+}
+
+const MAX_SCALE_PERCENT: f64 = 400.0;
+
+fn validate_scale_percent(options: &ConversionOptions, percent: f64) -> Result<(), String> {
+    if options.width.is_some() || options.height.is_some() {
+        return Err("Use either a scale percentage or an explicit width/height".to_string());
+    }
+    if !percent.is_finite() || percent <= 0.0 || percent > MAX_SCALE_PERCENT {
+        return Err(format!("Scale must be between 0 and {MAX_SCALE_PERCENT}%, got {percent}"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    // ConversionOptions field the frontend should highlight; "options" when it can't be pinned down
+    field: String,
+    message: String,
+}
+
+/// Every problem with the options at once, so the UI can flag them all before convert is pressed.
+#[tauri::command]
+fn validate_options(options: ConversionOptions) -> Vec<ValidationError> {
+    collect_validation_errors(&options)
+}
+
+fn collect_validation_errors(options: &ConversionOptions) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let mut fail = |field: &str, message: String| {
+        errors.push(ValidationError { field: field.to_string(), message })
+    };
+
+    let mut options = options.clone();
+    if let Err(e) = resolve_timestamp_strings(&mut options) {
+        fail("start", e);
+    }
+    if !std::path::Path::new(&options.input_path).is_file() {
+        fail("input_path", format!("Input file not found: {}", options.input_path));
+    }
+    let output_dir = std::path::Path::new(&options.output_path).parent();
+    if options.output_path.is_empty() || output_dir.is_some_and(|d| !d.as_os_str().is_empty() && !d.is_dir()) {
+        fail("output_path", format!("Output folder doesn't exist: {}", options.output_path));
+    }
+    if options.end_ms <= options.start_ms {
+        fail("end_ms", "Trim end must be after its start".to_string());
+    }
+
+    let format = options.format.as_deref().unwrap_or("mp4");
+    let allowed_video = video_codecs_for_format(format);
+    let allowed_audio = audio_codecs_for_format(format);
+    if allowed_video.is_empty() && allowed_audio.is_empty() {
+        fail("format", format!("Unsupported format: {format}"));
+    }
+    if let Some(ref codec) = options.video_codec {
+        if !options.is_audio_only && !allowed_video.contains(&codec.as_str()) {
+            fail("video_codec", format!("Video codec {codec} not allowed for format {format}"));
+        }
+    }
+    if let Some(ref codec) = options.audio_codec {
+        if !allowed_audio.contains(&codec.as_str()) {
+            fail("audio_codec", format!("Audio codec {codec} not allowed for format {format}"));
+        }
+        // an audio bitrate over the encoder's max isn't flagged, the builder clamps it
+    }
+
+    if let Some(vb) = options.video_bitrate_kbps {
+        if vb < MIN_VIDEO_BITRATE_KBPS {
+            fail("video_bitrate_kbps", format!("{vb} kbps is too low to encode any video"));
+        }
+        if options.max_bitrate_kbps.is_some_and(|max| max < vb) {
+            fail("max_bitrate_kbps", "Maximum bitrate is below the target bitrate".to_string());
+        }
+    }
+    // a bitrate typed in bps instead of kbps still encodes, until the disk fills up
+    let total_kbps = options.video_bitrate_kbps.unwrap_or(0).saturating_add(options.audio_bitrate_kbps.unwrap_or(0));
+    let estimated_bytes = total_kbps as f64 * 1000.0 / 8.0 * expected_output_duration(&options).max(0.0);
+    if estimated_bytes > MAX_ESTIMATED_OUTPUT_BYTES {
+        fail(
+            "video_bitrate_kbps",
+            format!("{total_kbps} kbps over this length would be a {:.0} GB file", estimated_bytes / 1e9),
+        );
+    }
+    if let Some(speed) = options.speed {
+        if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+            fail("speed", format!("Speed must be between {MIN_SPEED} and {MAX_SPEED}, got {speed}"));
+        }
+    }
+
+    if options.copy_streams && options.copy_video {
+        fail("copy_video", "copy_streams already copies the video".to_string());
+    }
+    if (options.copy_streams || options.copy_video) && changes_video(&options) {
+        fail("copy_streams", "Stream copy can't be combined with cropping, scaling, fps or speed changes".to_string());
+    }
+    if options.is_audio_only && options.still_image {
+        fail("still_image", "A still image can only be converted to a video".to_string());
+    }
+
+    // the builder knows the remaining, rarer combinations; its first complaint is usually one of
+    // the above, so it's only reported when nothing else was found
+    if errors.is_empty() {
+        if let Err(e) = build_ffmpeg_args(&options) {
+            errors.push(ValidationError { field: "options".to_string(), message: e });
+        }
+    }
+    errors
+}
+
+const MIN_VIDEO_BITRATE_KBPS: u64 = 16;
+const MAX_ESTIMATED_OUTPUT_BYTES: f64 = 1e12;
+
+fn nice_level(priority: Option<&str>) -> Result<i32, String> {
+    match priority.unwrap_or("normal") {
+        "low" => Ok(10),
+        "normal" => Ok(0),
+        // lowering the nice value needs root on unix, so don't pretend it worked
+        "high" if cfg!(unix) => Err("High priority isn't available here (raising it needs root), use low or normal".to_string()),
+        "high" => Ok(-5),
+        other => Err(format!("Unsupported priority: {other} (use low, normal or high)")),
+    }
+}
+
+// ffmpeg happily seeks past the end and writes an empty file, so catch that before it runs
+fn ensure_trim_within_source(options: &ConversionOptions, info: &MediaInfo) -> Result<(), String> {
+    if options.end_ms <= options.start_ms {
+        return Err(format!(
+            "Trim end ({}) must be after its start ({})",
+            format_timestamp(options.end_ms),
+            format_timestamp(options.start_ms)
+        ));
+    }
+    // stills and streams without a container duration report 0
+    if info.is_image || !info.duration_seconds.is_finite() || info.duration_seconds <= 0.0 {
+        return Ok(());
+    }
+    let source_ms = (info.duration_seconds * 1000.0).round() as u64;
+    if options.start_ms >= source_ms {
+        return Err(format!(
+            "Trim start {} is past the end of the source ({})",
+            format_timestamp(options.start_ms),
+            format_timestamp(source_ms)
+        ));
+    }
+    Ok(())
+}
+
+/// Turns scale_percent into concrete even width/height from the (rotation-corrected) source size.
+fn apply_scale_percent(options: &mut ConversionOptions, percent: f64, info: &MediaInfo) -> Result<(), String> {
+    validate_scale_percent(options, percent)?;
+    let (Some(w), Some(h)) = (info.width, info.height) else {
+        return Err("Source has no frame size to scale from".to_string());
+    };
+    let even = |v: u32| ((v as f64 * percent / 100.0 / 2.0).round() as u32 * 2).max(2);
+    // the scale sees the rotated frame when it runs second
+    let (w, h) = if rotate_first(options)? && options.rotate.is_some_and(|r| r % 180 == 90) { (h, w) } else { (w, h) };
+    options.width = Some(even(w));
+    options.height = Some(even(h));
+    options.scale_percent = None;
+    Ok(())
+}
+
+const SCALE_ALGORITHMS: [&str; 11] = [
+    "fast_bilinear", "bilinear", "bicubic", "experimental", "neighbor", "area", "bicublin", "gauss", "sinc", "lanczos", "spline",
+];
+
+// empty without a choice, which leaves ffmpeg on its bicubic default
+fn scale_flags(options: &ConversionOptions) -> Result<String, String> {
+    match options.scale_algorithm.as_deref() {
+        None => Ok(String::new()),
+        Some(algorithm) if SCALE_ALGORITHMS.contains(&algorithm) => Ok(format!(":flags={algorithm}")),
+        Some(other) => Err(format!("Unknown scaling algorithm: {other} (use one of {})", SCALE_ALGORITHMS.join(", "))),
+    }
+}
+
+fn rotate_filter(degrees: u32) -> Result<&'static str, String> {
+    match degrees {
+        90 => Ok("transpose=clock"),
+        180 => Ok("hflip,vflip"),
+        270 => Ok("transpose=cclock"),
+        other => Err(format!("Rotation must be 90, 180 or 270 degrees, got {other}")),
+    }
+}
+
+fn rotate_first(options: &ConversionOptions) -> Result<bool, String> {
+    match options.transform_order.as_deref().unwrap_or("scale_first") {
+        "scale_first" => Ok(false),
+        "rotate_first" => Ok(true),
+        other => Err(format!("Unsupported transform order: {other} (use scale_first or rotate_first)")),
+    }
+}
+
+/// Exact broadcast rates as fractions, 29.97 as a float drifts by a frame every few minutes.
+fn fps_standard_rate(name: &str) -> Result<&'static str, String> {
+    match name {
+        "film" => Ok("24000/1001"),
+        "pal" => Ok("25"),
+        "ntsc" => Ok("30000/1001"),
+        "hfr" => Ok("60"),
+        other => Err(format!("Unknown frame rate standard: {other}")),
+    }
+}
+
+// The rate handed to the fps/minterpolate filters, as ffmpeg expects it on the command line.
+fn target_fps(options: &ConversionOptions) -> Result<Option<String>, String> {
+    match options.fps_standard {
+        Some(ref name) => fps_standard_rate(name).map(|r| Some(r.to_string())),
+        None => Ok(options.fps.map(|f| f.to_string())),
+    }
+}
+
+/// Whether the source actually needs its pixels moved to bt709. Untagged sources are assumed to be
+/// bt709 already, which is what every player does with them too.
+fn needs_bt709_conversion(info: &MediaInfo) -> Result<bool, String> {
+    // PQ/HLG needs real tone mapping, a plain zscale to bt709 just clips the highlights
+    if matches!(info.color_transfer.as_deref(), Some("smpte2084") | Some("arib-std-b67")) {
+        return Err("HDR sources need tone mapping, converting them straight to bt709 isn't supported".to_string());
+    }
+    let is_709 = |tag: &Option<String>| tag.as_deref().is_none_or(|t| t == "bt709");
+    Ok(!(is_709(&info.color_primaries) && is_709(&info.color_transfer) && is_709(&info.color_space)))
+}
+
+/// The options as the builder sees them, with the platform preset filled in and a waveform or
+/// spectrum mode turned into its graph. Anything that adds to the args from outside the builder
+/// has to look at these, not the raw options.
+fn with_presets_applied(options: &ConversionOptions) -> Result<ConversionOptions, String> {
+    let mut applied = match options.platform_preset {
+        Some(ref name) => apply_platform_preset(options, name)?,
+        None => options.clone(),
+    };
+    if matches!(applied.audio_video_mode.as_deref(), Some("waveform" | "spectrum")) {
+        applied = apply_audio_visual(&applied)?;
+    }
+    Ok(applied)
+}
+
+fn build_ffmpeg_args(options: &ConversionOptions) -> Result<Vec<String>, String> {
+    build_ffmpeg_args_noted(options, &mut Vec::new())
+}
+
+/// Same as build_ffmpeg_args, but anything the builder quietly adjusted (odd sizes rounded down
+/// and the like) is pushed to `notes`, so the conversion result can tell the user about it.
+fn build_ffmpeg_args_noted(options: &ConversionOptions, notes: &mut Vec<String>) -> Result<Vec<String>, String> {
+    let applied = with_presets_applied(options)?;
+    let options = &applied;
+    let cover_as_video = match options.audio_video_mode.as_deref() {
+        None | Some("none") | Some("waveform" | "spectrum") => false,
+        Some("cover") => {
+            if options.cover_image.is_none() || options.is_audio_only || options.copy_streams || options.still_image {
+                return Err("Cover mode needs a cover_image and a video output".to_string());
+            }
+            if options.filter_complex.is_some() || options.audio_offset_ms.is_some_and(|o| o > 0) {
+                return Err("Cover mode can't be combined with filter_complex or a positive audio offset".to_string());
+            }
+            true
+        }
+        Some(other) => {
+            return Err(format!("Unsupported audio video mode: {other} (use none, cover, waveform or spectrum)"))
+        }
+    };
+
+    let mut args: Vec<String> = Vec::new();
+    args.push("-y".to_string());
+
+    let format = options.format.as_deref().unwrap_or("mp4");
+    if let Some(speed) = options.speed {
+        if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+            return Err(format!("Speed must be between {MIN_SPEED} and {MAX_SPEED}, got {speed}"));
+        }
+    }
+    if let Some(preview) = options.preview_duration_secs {
+        if !preview.is_finite() || preview <= 0.0 {
+            return Err(format!("Preview length must be positive, got {preview}"));
+        }
+    }
+    if let Some(max) = options.max_duration_secs {
+        if !max.is_finite() || max <= 0.0 {
+            return Err(format!("Maximum duration must be positive, got {max}"));
+        }
+    }
+    if options.interpolate {
+        if options.is_audio_only || options.copy_streams || options.still_image {
+            return Err("Frame interpolation only applies when re-encoding video".to_string());
+        }
+        if target_fps(options)?.is_none() {
+            return Err("Frame interpolation needs a target fps".to_string());
+        }
+    }
+    if let Some(ref graph) = options.filter_complex {
+        if graph.trim().is_empty() {
+            return Err("filter_complex can't be empty".to_string());
+        }
+        if options.maps.as_ref().is_none_or(|m| m.is_empty() || m.iter().any(|m| m.trim().is_empty())) {
+            return Err("filter_complex needs maps for the streams to write, e.g. [v] and [a]".to_string());
+        }
+        if options.copy_streams || options.copy_video || uses_offset_audio_input(options, format) {
+            return Err("filter_complex can't be combined with stream copy or a positive audio offset".to_string());
+        }
+    }
+    if let Some(ref cover) = options.cover_image {
+        if !std::path::Path::new(cover).is_file() {
+            return Err(format!("Cover image not found: {cover}"));
+        }
+        if !cover_as_video && (options.still_image || !matches!(format, "mp4" | "mov" | "m4a" | "mp3" | "flac")) {
+            return Err(format!("Cover art can't be embedded in {format}"));
+        }
+    }
+    if options.inject_360_metadata
+        && (options.copy_streams || options.copy_video || options.is_audio_only || !matches!(format, "mp4" | "mov"))
+    {
+        return Err("360 metadata can only be written into a re-encoded mp4 or mov".to_string());
+    }
+    if options.rotate.is_some() && (options.copy_streams || options.copy_video || options.is_audio_only) {
+        return Err("Rotating needs a re-encode of the video".to_string());
+    }
+    if options.reverse {
+        if options.copy_streams || options.copy_video || options.still_image {
+            return Err("Reversing needs a re-encode of the source".to_string());
+        }
+        if options.audio_offset_ms.is_some() {
+            return Err("Audio offset can't be combined with reverse".to_string());
+        }
+        let trimmed_secs = options.end_ms.saturating_sub(options.start_ms) / 1000;
+        if trimmed_secs > MAX_REVERSE_SECS {
+            return Err(format!(
+                "Reverse is limited to {MAX_REVERSE_SECS}s since every frame is kept in memory, trim the clip first"
+            ));
+        }
+    }
+    let absolute_end = match options.end_mode.as_deref().unwrap_or("duration") {
+        "duration" => false,
+        "absolute" => true,
+        other => return Err(format!("Unsupported end mode: {other} (use duration or absolute)")),
+    };
+    // output -ss and -to are compared against the filtered timestamps, so anything that retimes
+    // the stream or needs its own input-side cut would land on the wrong frames
+    if options.precise_seek
+        && (options.speed.is_some() || options.reverse || options.still_image || uses_offset_audio_input(options, format))
+    {
+        return Err("Precise seek can't be combined with speed changes, reverse, stills or a positive audio offset".to_string());
+    }
+    let output_duration = expected_output_duration(options);
+    if !output_duration.is_finite() || output_duration <= 0.0 {
+        return Err(format!("Output duration must be positive, got {output_duration:.3}s"));
+    }
+    let output_duration_ms = (output_duration * 1000.0).round() as u64;
+    let hybrid_preroll = hybrid_preroll_ms(options, format);
+
+    if options.still_image {
+        if options.is_audio_only || options.copy_streams {
+            return Err("A still image can only be converted to a video".to_string());
+        }
+        // loop the single frame; -t below then sets how long the video runs
+        args.push("-loop".to_string());
+        args.push("1".to_string());
+    } else if options.start_ms > 0 && !options.precise_seek && sample_trim_range(options).is_none() {
+        let input_seek_ms = options.start_ms - hybrid_preroll.unwrap_or(0);
+        if input_seek_ms > 0 {
+            args.push("-ss".to_string());
+            args.push(format_timestamp(input_seek_ms));
+        }
+    }
+    if let Some(ref hw) = options.hw_decode {
+        args.extend(build_hw_decode_args(hw, options, format)?);
+    }
+    if options.ignore_edit_list {
+        args.push("-ignore_editlist".to_string());
+        args.push("1".to_string());
+    }
+    // a sample-accurate trim has no input -ss, so this -t would count from the top of the file;
+    // atrim runs before areverse there and already drops everything past the end
+    if options.reverse && sample_trim_range(options).is_none() {
+        // the output -t would keep the start of the reversed stream, i.e. the end of the file,
+        // so stop reading at end_ms instead
+        args.push("-t".to_string());
+        args.push(format_timestamp(options.end_ms.saturating_sub(options.start_ms)));
+    }
+
+    args.push("-i".to_string());
+    args.push(options.input_path.clone());
+
+    // every -i has to come before the -map options that point at it, so the maps are collected
+    // here and pushed once all inputs are in
+    let mut maps: Vec<String> = Vec::new();
+
+    // a positive audio delay opens the source a second time with its timestamps shifted and takes
+    // the audio from there; negative offsets are trimmed off in the audio filters instead
+    if uses_offset_audio_input(options, format) {
+        let offset_ms = options.audio_offset_ms.unwrap_or(0);
+        args.push("-itsoffset".to_string());
+        args.push(format_timestamp(offset_ms as u64));
+        if options.start_ms > 0 {
+            args.push("-ss".to_string());
+            args.push(format_timestamp(options.start_ms));
+        }
+        args.push("-i".to_string());
+        args.push(options.input_path.clone());
+        maps.extend(["-map", "0:v:0", "-map", "1:a:0?"].map(String::from));
+    }
+
+    if let (Some(cover), true) = (&options.cover_image, cover_as_video) {
+        // the picture becomes the video track; -t below or -shortest ends the endless loop
+        args.extend(["-loop", "1", "-i", cover].map(String::from));
+        maps.extend(["-map", "1:v:0", "-map", "0:a:0", "-shortest"].map(String::from));
+    } else if let Some(ref cover) = options.cover_image {
+        let cover_input = if uses_offset_audio_input(options, format) { 2 } else { 1 };
+        args.push("-i".to_string());
+        args.push(cover.clone());
+        // any -map turns off ffmpeg's default stream picks, so spell out the main ones unless
+        // the offset input or a user graph already did
+        if !uses_offset_audio_input(options, format) && options.filter_complex.is_none() {
+            let main = if options.is_audio_only { vec!["0:a:0"] } else { vec!["0:v:0", "0:a:0?"] };
+            for m in main {
+                maps.push("-map".to_string());
+                maps.push(m.to_string());
+            }
+        }
+        maps.push("-map".to_string());
+        maps.push(format!("{cover_input}:v:0"));
+    }
+    if let Some(ref chapters) = options.import_chapters {
+        if options.strip_metadata {
+            return Err("Imported chapters would be dropped again by strip_metadata".to_string());
+        }
+        if !std::path::Path::new(chapters).is_file() {
+            return Err(format!("Chapters file not found: {chapters}"));
+        }
+        let chapters_input = 1 + uses_offset_audio_input(options, format) as usize + options.cover_image.is_some() as usize;
+        args.extend(["-f", "ffmetadata", "-i", chapters].map(String::from));
+        maps.push("-map_metadata".to_string());
+        maps.push(chapters_input.to_string());
+        maps.push("-map_chapters".to_string());
+        maps.push(chapters_input.to_string());
+    }
+    args.extend(maps);
+
+    if options.precise_seek && options.start_ms > 0 && sample_trim_range(options).is_none() {
+        args.push("-ss".to_string());
+        args.push(format_timestamp(options.start_ms));
+    } else if let Some(preroll) = hybrid_preroll {
+        // the input timeline starts preroll before start_ms, drop that part on the exact frame
+        args.push("-ss".to_string());
+        args.push(format_timestamp(preroll));
+    }
+
+    // -t is an output option here, so it's measured after any speed change. -to is an absolute
+    // position, but an input -ss resets the timeline to zero, so it's only start-based when the
+    // seek happens on the output side
+    match (absolute_end, options.precise_seek) {
+        // atrim already ends on the exact sample, a -t on top would round it off
+        _ if sample_trim_range(options).is_some() => {}
+        (false, _) => {
+            args.push("-t".to_string());
+            args.push(format_timestamp(output_duration_ms));
+        }
+        (true, true) => {
+            args.push("-to".to_string());
+            args.push(format_timestamp(options.start_ms + output_duration_ms));
+        }
+        (true, false) => {
+            args.push("-to".to_string());
+            args.push(format_timestamp(hybrid_preroll.unwrap_or(0) + output_duration_ms));
+        }
+    }
+
+    if options.copy_streams {
+        if options.audio_offset_ms.is_some_and(|o| o < 0) {
+            return Err("Moving audio earlier needs re-encoding, it can't be done with stream copy".to_string());
+        }
+        if changes_video(options) {
+            return Err("Stream copy can't be combined with cropping, scaling, fps or speed changes".to_string());
+        }
+        if options.is_audio_only && options.cover_image.is_none() {
+            args.push("-vn".to_string());
+        }
+        args.push("-c".to_string());
+        args.push("copy".to_string());
+        // a remux of a file that already has moov up front doesn't need the extra faststart rewrite
+        if matches!(format, "mp4" | "mov" | "m4a") && source_has_faststart(&options.input_path) != Some(true) {
+            args.push("-movflags".to_string());
+            args.push("+faststart".to_string());
+        }
+        args.extend(build_cover_args(options));
+        args.extend(build_attachment_args(&args, format, options));
+        args.extend(build_metadata_args(options));
+        args.extend(build_muxer_args(format, options)?);
+        args.push(options.output_path.clone());
+        return Ok(args);
+    }
+
+    // video passes through untouched, only the audio is re-encoded
+    if options.copy_video {
+        if options.is_audio_only || options.still_image || format == "gif" {
+            return Err("Copying video needs a video output".to_string());
+        }
+        if changes_video(options) || options.interpolate || options.timecode_overlay {
+            return Err("Copying video can't be combined with video filters, re-encode it instead".to_string());
+        }
+        args.push("-c:v".to_string());
+        args.push("copy".to_string());
+        let allowed_audio = audio_codecs_for_format(format);
+        let audio_codec = match options.audio_codec {
+            Some(ref user) if allowed_audio.iter().any(|c| c == user) => Some(user.as_str()),
+            Some(ref user) => return Err(format!("Audio codec {user} not allowed for format {format}")),
+            None => allowed_audio.first().copied(),
+        };
+        match audio_codec {
+            Some(ac) => args.extend(build_audio_args(ac, format, options, notes)?),
+            None => args.push("-an".to_string()),
+        }
+        if matches!(format, "mp4" | "mov") {
+            args.push("-movflags".to_string());
+            args.push("+faststart".to_string());
+        }
+        args.extend(build_cover_args(options));
+        args.extend(build_attachment_args(&args, format, options));
+        args.extend(build_metadata_args(options));
+        args.extend(build_muxer_args(format, options)?);
+        args.push(options.output_path.clone());
+        return Ok(args);
+    }
+
+    if options.is_audio_only {
+        let allowed_audio = audio_codecs_for_format(format);
+        if allowed_audio.is_empty() {
+            return Err(format!("No audio codecs available for format: {format}"));
+        }
+        let audio_codec = if let Some(ref user) = options.audio_codec {
+            if allowed_audio.iter().any(|c| c == user) {
+                user.as_str()
+            } else {
+                return Err(format!("Audio codec {user} not allowed for format {format}"));
+            }
+        } else {
+            allowed_audio[0]
+        };
+        if options.filter_complex.is_some() {
+            push_filter_complex(&mut args, options);
+        } else if options.cover_image.is_none() {
+            args.push("-vn".to_string());
+        }
+        args.extend(build_audio_args(audio_codec, format, options, notes)?);
+        if let Some(muxer) = raw_pcm_muxer(format) {
+            // there's no header to carry the layout, whoever reads the file has to be told
+            if options.sample_rate.is_none() || options.channels.is_none() {
+                return Err(format!("Raw {format} output needs an explicit sample rate and channel count"));
+            }
+            args.push("-f".to_string());
+            args.push(muxer.to_string());
+        }
+    } else {
+        let mut filters: Vec<String> = Vec::new();
+        // deinterlace first, scaling interlaced fields mixes them together
+        if let Some(ref mode) = options.deinterlace {
+            filters.push(deinterlace_filter(mode)?.to_string());
+        }
+        if let Some(ref crop) = options.crop {
+            filters.push(format!("crop={}:{}:{}:{}", crop.width, crop.height, crop.x, crop.y));
+        }
+        let rotation = options.rotate.map(rotate_filter).transpose()?;
+        let rotated_first = rotate_first(options)?;
+        if let (Some(rotation), true) = (rotation, rotated_first) {
+            filters.push(rotation.to_string());
+        }
+        // ffmpeg autorotates before the filter chain, so width/height are in display orientation,
+        // matching the rotation-corrected size analyze_media reports
+        if let (Some(w), Some(h)) = (options.width, options.height) {
+            // 4:2:0 and 4:2:2 encoders refuse odd sizes with a cryptic error, so round down instead;
+            // gif has no chroma subsampling and keeps the exact size
+            let (even_w, even_h) = if format == "gif" { (w, h) } else { ((w & !1).max(2), (h & !1).max(2)) };
+            if (even_w, even_h) != (w, h) {
+                notes.push(format!("Rounded {w}x{h} down to {even_w}x{even_h}, the encoder needs even dimensions"));
+            }
+            filters.push(format!("scale={even_w}:{even_h}{}", scale_flags(options)?));
+        } else if let Some(percent) = options.scale_percent {
+            // callers that didn't probe the source first get the same even rounding done by ffmpeg
+            validate_scale_percent(options, percent)?;
+            let factor = percent / 100.0;
+            filters.push(format!("scale=trunc(iw*{factor}/2)*2:trunc(ih*{factor}/2)*2{}", scale_flags(options)?));
+        } else if options.still_image {
+            // photos often have odd dimensions, which yuv420p encoders refuse
+            filters.push(format!("scale=trunc(iw/2)*2:trunc(ih/2)*2{}", scale_flags(options)?));
+        }
+        if let (Some(rotation), false) = (rotation, rotated_first) {
+            filters.push(rotation.to_string());
         }
+        if options.convert_to_bt709 {
+            filters.push("zscale=primaries=709:transfer=709:matrix=709".to_string());
+        }
+        // after scaling so the buffered frames are as small as they'll get
+        if options.reverse {
+            filters.push("reverse".to_string());
+        }
+        if let Some(speed) = options.speed {
+            filters.push(format!("setpts=PTS/{speed}"));
+        }
+        // after scale on purpose, minterpolate's cost grows with the frame size
+        match target_fps(options)? {
+            Some(fps) if options.interpolate => {
+                filters.push(format!("minterpolate=fps={fps}:mi_mode=mci:mc_mode=aobmc:me_mode=bidir:vsbmc=1"))
+            }
+            Some(fps) => filters.push(format!("fps={fps}")),
+            None => {}
+        }
+        if options.timecode_overlay {
+            filters.push(build_timecode_filter(options)?);
+        }
+        if format == "gif" {
+            filters.push(build_gif_palette_filter(options)?);
+        }
+        if options.filter_complex.is_some() {
+            push_filter_complex(&mut args, options);
+        } else if !filters.is_empty() {
+            // plain -vf would run the chain over the cover picture too
+            let flag = if options.cover_image.is_some() && !cover_as_video { "-filter:v:0" } else { "-vf" };
+            args.push(flag.to_string());
+            args.push(filters.join(","));
+        }
+
+        let allowed_video = video_codecs_for_format(format);
+        if allowed_video.is_empty() {
+            return Err(format!("No video codecs available for format: {format}"));
+        }
+        let mut video_codec = if let Some(ref user) = options.video_codec {
+            if allowed_video.iter().any(|c| c == user) {
+                user.as_str()
+            } else {
+                return Err(format!("Video codec {user} not allowed for format {format}"));
+            }
+        } else {
+            allowed_video[0]
+        };
+
+        let mut audio_codec: Option<&str>;
+        let mut add_x264_preset = true;
+        let mut pix_fmt: Option<&str> = Some("yuv420p");
+        if options.preserve_hdr_metadata {
+            if video_codec != "libx265" {
+                return Err("HDR metadata can only be carried over when encoding with libx265".to_string());
+            }
+            // HDR10 is 10-bit by definition, 8-bit output would band and some players reject it
+            pix_fmt = Some("yuv420p10le");
+        }
+        let mut extra: Vec<String> = Vec::new();
+
+        let allowed_audio = audio_codecs_for_format(format);
+        audio_codec = if let Some(ref user) = options.audio_codec {
+            if allowed_audio.iter().any(|c| c == user) {
+                Some(user.as_str())
+            } else if !allowed_audio.is_empty() {
+                Some(allowed_audio[0])
+            } else {
+                None
+            }
+        } else {
+            allowed_audio.first().copied()
+        };
+
+        // GIF picks its own size from the palette, and ProRes/DNxHR quality comes from the profile
+        let mut use_bitrate = video_codec != "gif";
+
+        if video_codec == "prores_ks" {
+            let (profile, fmt) = prores_profile(options.video_profile.as_deref())?;
+            pix_fmt = Some(fmt);
+            extra.push("-profile:v".to_string());
+            extra.push(profile.to_string());
+            use_bitrate = false;
+        } else if video_codec == "dnxhd" {
+            let profile = options.video_profile.as_deref().unwrap_or("dnxhr_hq");
+            pix_fmt = Some(dnx_pix_fmt(profile)?);
+            if profile == "dnxhd" {
+                validate_dnxhd_combo(options)?;
+            } else {
+                use_bitrate = false;
+            }
+            extra.push("-profile:v".to_string());
+            extra.push(profile.to_string());
+        } else if video_codec == "mjpeg" {
+            pix_fmt = Some("yuvj422p");
+        }
+
+        match format {
+            // the spherical box is appended to moov afterwards, which is only safe while moov sits
+            // after mdat, so those outputs skip faststart
+            "mp4" | "mov" if options.inject_360_metadata => {
+                extra.push("-strict".to_string());
+                extra.push("unofficial".to_string());
+            }
+            "mp4" | "mov" => {
+                extra.push("-movflags".to_string());
+                extra.push("+faststart".to_string());
+            }
+            // audio was already picked from audio_codecs_for_format above, honoring a valid user choice
+            "mkv" | "avi" | "flv" => {}
+            "webm" => {
+                video_codec = "libvpx-vp9";
+                add_x264_preset = false;
+            }
+            "gif" => {
+                video_codec = "gif";
+                audio_codec = None;
+                add_x264_preset = false;
+                // paletteuse already outputs pal8 frames
+                pix_fmt = None;
+                extra.push("-an".to_string());
+                extra.push("-loop".to_string());
+                extra.push("0".to_string());
+            }
+            other => return Err(format!("Unsupported format: {other}")),
+        }
+
+        if options.still_image && audio_codec.is_some() {
+            audio_codec = None;
+            extra.push("-an".to_string());
+        }
+
+        args.push("-c:v".to_string());
+        args.push(video_codec.to_string());
+        if add_x264_preset && video_codec == "libx264" {
+            args.push("-preset".to_string());
+            args.push(options.preset.clone().unwrap_or_else(|| "medium".to_string()));
+        }
+
+        let encoder_params = match video_codec {
+            "libx264" => options.x264_params.as_ref().map(|p| ("-x264-params", p)),
+            "libx265" => options.x265_params.as_ref().map(|p| ("-x265-params", p)),
+            _ => None,
+        };
+        if let Some((flag, params)) = encoder_params {
+            validate_encoder_params(params)?;
+            args.push(flag.to_string());
+            args.push(params.clone());
+        }
+
+        if options.low_latency {
+            let latency = low_latency_args(video_codec, options.seek_friendly);
+            if latency.is_empty() {
+                return Err(format!("Low latency isn't supported for {video_codec}"));
+            }
+            args.extend(latency);
+        } else if options.seek_friendly {
+            args.extend(seek_friendly_args(video_codec));
+        }
+
+        if let Some(vb) = options.video_bitrate_kbps {
+            if use_bitrate {
+                args.push("-b:v".to_string());
+                args.push(format!("{vb}k"));
+            }
+        }
+
+        if let Some(max) = options.max_bitrate_kbps {
+            if video_codec != "gif" {
+                // bufsize defaults to 2x maxrate, which is what most streaming guides recommend
+                let bufsize = options.buffer_size_kbps.unwrap_or(max * 2);
+                args.push("-maxrate".to_string());
+                args.push(format!("{max}k"));
+                args.push("-bufsize".to_string());
+                args.push(format!("{bufsize}k"));
+            }
+        }
+
+        if let Some(ac) = audio_codec {
+            args.extend(build_audio_args(ac, format, options, notes)?);
+        }
+
+        args.extend(extra);
+
+        if (options.force_bt709_tags || options.convert_to_bt709) && video_codec != "gif" {
+            args.extend(["-color_primaries", "bt709", "-color_trc", "bt709", "-colorspace", "bt709"].map(String::from));
+        }
+
+        if let Some(fmt) = pix_fmt {
+            let flag = if options.cover_image.is_some() { "-pix_fmt:v:0" } else { "-pix_fmt" };
+            args.push(flag.to_string());
+            args.push(fmt.to_string());
+        }
+
+        // the fps filter already produces the rate, -r makes the muxer tag it exactly instead of rounding
+        if let Some(rate) = options.fps_standard.as_deref().map(fps_standard_rate).transpose()? {
+            args.push("-r".to_string());
+            args.push(rate.to_string());
+        }
+    }
+
+    args.extend(build_cover_args(options));
+    args.extend(build_attachment_args(&args, format, options));
+    args.extend(build_metadata_args(options));
+    args.extend(build_muxer_args(format, options)?);
+    args.push(options.output_path.clone());
+    Ok(args)
+}
+
+// The cover is the last video stream: after the main video, or the only one for audio outputs.
+fn build_cover_args(options: &ConversionOptions) -> Vec<String> {
+    if options.cover_image.is_none() || options.audio_video_mode.as_deref() == Some("cover") {
+        return Vec::new();
+    }
+    let index = if options.is_audio_only { 0 } else { 1 };
+    vec![
+        format!("-c:v:{index}"),
+        "mjpeg".to_string(),
+        format!("-disposition:v:{index}"),
+        "attached_pic".to_string(),
+    ]
+}
+
+#[derive(Debug, Serialize)]
+pub struct Attachment {
+    filename: String,
+    mimetype: Option<String>,
+}
+
+/// Files attached to an mkv, usually the fonts its styled subtitles need.
+#[tauri::command]
+async fn list_attachments(window: tauri::Window, path: String) -> Result<Vec<Attachment>, String> {
+    ensure_safe_paths(&window, &[&path], &[])?;
+    let json = probe_json(path).await?;
+    Ok(json["streams"]
+        .as_array()
+        .map(|streams| {
+            streams
+                .iter()
+                .filter(|s| s["codec_type"] == "attachment")
+                .map(|s| Attachment {
+                    filename: s["tags"]["filename"].as_str().unwrap_or("unnamed").to_string(),
+                    mimetype: s["tags"]["mimetype"].as_str().map(str::to_string),
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn build_attachment_args(args: &[String], format: &str, options: &ConversionOptions) -> Vec<String> {
+    if !options.preserve_attachments {
+        return Vec::new();
+    }
+    if format != "mkv" {
+        println!("preserve_attachments ignored: {format} can't carry attachments");
+        return Vec::new();
+    }
+    let mut out = Vec::new();
+    // mapping the attachments turns off the default stream picks, so name the usual ones unless
+    // something earlier already mapped streams. Subtitles come along since the fonts are for them
+    if !args.iter().any(|a| a == "-map") {
+        let streams: &[&str] = if options.is_audio_only { &["0:a:0?", "0:s?"] } else { &["0:v:0?", "0:a:0?", "0:s?"] };
+        for stream in streams {
+            out.push("-map".to_string());
+            out.push(stream.to_string());
+        }
+        // mkv's default subtitle encoder can't turn bitmap subs into text, and the ass styling
+        // the fonts are for would be lost anyway, so pass them through untouched
+        out.extend(["-c:s", "copy"].map(String::from));
+    }
+    out.extend(["-map", "0:t?", "-c:t", "copy"].map(String::from));
+    out
+}
+
+// -map_metadata -1 also turns off the per-stream and chapter copies, which is where phones put
+// GPS (location, com.apple.quicktime.location.ISO6709) and creation_time. bitexact keeps the
+// muxer from writing its own encoder tag.
+fn build_metadata_args(options: &ConversionOptions) -> Vec<String> {
+    if !options.strip_metadata {
+        return options
+            .extra_metadata
+            .iter()
+            .flat_map(|(key, value)| ["-metadata".to_string(), format!("{key}={value}")])
+            .collect();
+    }
+    ["-map_metadata", "-1", "-map_chapters", "-1", "-fflags", "+bitexact"]
+        .map(String::from)
+        .to_vec()
+}
+
+// Muxer-level flags worth exposing per output format. Anything else is rejected up front, a typo
+// would otherwise only show up as an ffmpeg "Unrecognized option" failure.
+fn muxer_options_for_format(fmt: &str) -> Vec<&'static str> {
+    match fmt {
+        "mp4" | "mov" | "m4a" => vec!["frag_duration", "min_frag_duration", "frag_size", "movflags", "brand", "use_editlist"],
+        "mkv" | "webm" => vec!["cluster_time_limit", "cluster_size_limit", "reserve_index_space", "cues_to_front"],
+        "avi" => vec!["reserve_index_entries", "write_channel_mask"],
+        "flv" => vec!["flvflags"],
+        "gif" => vec!["loop", "final_delay"],
+        "mp3" => vec!["id3v2_version", "write_id3v1", "write_xing"],
+        "ogg" | "opus" => vec!["page_duration", "serial_offset"],
+        "wav" => vec!["write_bext", "rf64"],
+        "flac" => vec!["write_header"],
+        _ => vec![],
+    }
+}
+
+fn build_muxer_args(format: &str, options: &ConversionOptions) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    if options.avoid_negative_ts {
+        args.push("-avoid_negative_ts".to_string());
+        args.push("make_zero".to_string());
+    }
+    let Some(ref muxer_options) = options.muxer_options else {
+        return Ok(args);
+    };
+    let allowed = muxer_options_for_format(format);
+    let mut keys: Vec<&String> = muxer_options.keys().collect();
+    // HashMap order is random, keep the command line stable between runs
+    keys.sort();
+
+    for key in keys {
+        if !allowed.contains(&key.as_str()) {
+            return Err(format!("Muxer option {key} is not supported for {format}"));
+        }
+        args.push(format!("-{key}"));
+        args.push(muxer_options[key].clone());
+    }
+    Ok(args)
+}
+
+// Spherical Video V1 uuid box, the one YouTube and Facebook look for.
+const SPHERICAL_UUID: [u8; 16] = [
+    0xff, 0xcc, 0x82, 0x63, 0xf8, 0x55, 0x4a, 0x93, 0x88, 0x14, 0x58, 0x7a, 0x02, 0x52, 0x1f, 0xdd,
+];
+const SPHERICAL_XML: &str = concat!(
+    "<?xml version=\"1.0\"?><rdf:SphericalVideo xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\" ",
+    "xmlns:GSpherical=\"http://ns.google.com/videos/1.0/spherical/\">",
+    "<GSpherical:Spherical>true</GSpherical:Spherical><GSpherical:Stitched>true</GSpherical:Stitched>",
+    "<GSpherical:StitchingSoftware>xhMPEG</GSpherical:StitchingSoftware>",
+    "<GSpherical:ProjectionType>equirectangular</GSpherical:ProjectionType></rdf:SphericalVideo>"
+);
+
+/// Appends the equirectangular uuid box to the video trak. Only handles a trailing moov: growing
+/// it in place then doesn't move mdat, so no chunk offsets need patching.
+fn inject_spherical_metadata(path: &std::path::Path) -> Result<(), String> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let err = |e: std::io::Error| format!("Failed to write 360 metadata: {e}");
+    let mut file = std::fs::OpenOptions::new().read(true).write(true).open(path).map_err(err)?;
+    let file_len = file.metadata().map_err(err)?.len();
+
+    // find moov among the top-level boxes, with 32-bit sizes only (ffmpeg never writes large moovs)
+    let mut offset = 0u64;
+    let moov_offset = loop {
+        if offset + 8 > file_len {
+            return Err("No moov box found in the output".to_string());
+        }
+        file.seek(SeekFrom::Start(offset)).map_err(err)?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header).map_err(err)?;
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let size = match size {
+            0 => file_len - offset,
+            1 => {
+                let mut large = [0u8; 8];
+                file.read_exact(&mut large).map_err(err)?;
+                u64::from_be_bytes(large)
+            }
+            n => n,
+        };
+        if &header[4..8] == b"moov" {
+            if offset + size != file_len {
+                return Err("moov has to be the last box to add 360 metadata".to_string());
+            }
+            break offset;
+        }
+        if size < 8 {
+            return Err("Output has a malformed box".to_string());
+        }
+        offset += size;
+    };
+
+    let mut moov = Vec::new();
+    file.seek(SeekFrom::Start(moov_offset)).map_err(err)?;
+    file.read_to_end(&mut moov).map_err(err)?;
+    if moov[..4] == 1u32.to_be_bytes() {
+        return Err("Outputs with a 64-bit moov size aren't supported for 360 metadata".to_string());
+    }
+
+    let mut uuid_box = Vec::new();
+    uuid_box.extend_from_slice(&((8 + 16 + SPHERICAL_XML.len()) as u32).to_be_bytes());
+    uuid_box.extend_from_slice(b"uuid");
+    uuid_box.extend_from_slice(&SPHERICAL_UUID);
+    uuid_box.extend_from_slice(SPHERICAL_XML.as_bytes());
+
+    let (trak_start, trak_end) = child_boxes(&moov, 8)
+        .into_iter()
+        .find(|&(start, end)| &moov[start + 4..start + 8] == b"trak" && is_video_trak(&moov[start..end]))
+        .ok_or_else(|| "No video track found in the output".to_string())?;
+
+    let grow = uuid_box.len() as u32;
+    for box_start in [0, trak_start] {
+        let size = u32::from_be_bytes(moov[box_start..box_start + 4].try_into().unwrap_or_default());
+        moov[box_start..box_start + 4].copy_from_slice(&(size + grow).to_be_bytes());
+    }
+    moov.splice(trak_end..trak_end, uuid_box);
+
+    file.seek(SeekFrom::Start(moov_offset)).map_err(err)?;
+    file.write_all(&moov).map_err(err)?;
+    Ok(())
+}
+
+// (start, end) of every box inside `data[from..]`, stopping at anything that doesn't add up.
+fn child_boxes(data: &[u8], from: usize) -> Vec<(usize, usize)> {
+    let mut boxes = Vec::new();
+    let mut offset = from;
+    while offset + 8 <= data.len() {
+        let size = u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        if size < 8 || offset + size > data.len() {
+            break;
+        }
+        boxes.push((offset, offset + size));
+        offset += size;
+    }
+    boxes
+}
+
+// trak > mdia > hdlr, whose handler type sits after version/flags and pre_defined
+fn is_video_trak(trak: &[u8]) -> bool {
+    let kind = |data: &[u8], start: usize, name: &[u8]| &data[start + 4..start + 8] == name;
+    child_boxes(trak, 8)
+        .into_iter()
+        .filter(|&(start, _)| kind(trak, start, b"mdia"))
+        .any(|(start, end)| {
+            let mdia = &trak[start..end];
+            child_boxes(mdia, 8)
+                .into_iter()
+                .any(|(h, h_end)| kind(mdia, h, b"hdlr") && h_end - h >= 20 && &mdia[h + 16..h + 20] == b"vide")
+        })
+}
+
+/// Walks the top-level MP4/MOV atoms and reports whether `moov` comes before `mdat`.
+/// Returns None when the file isn't an ISO-BMFF container or can't be read.
+fn source_has_faststart(path: &str) -> Option<bool> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path).ok()?;
+    let file_len = file.metadata().ok()?.len();
+    let mut offset = 0u64;
+
+    while offset + 8 <= file_len {
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header).ok()?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let kind = &header[4..8];
+
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large).ok()?;
+            size = u64::from_be_bytes(large);
+        } else if size == 0 {
+            size = file_len - offset;
+        }
+
+        match kind {
+            b"moov" => return Some(true),
+            b"mdat" => return Some(false),
+            _ => {}
+        }
+        if size < 8 {
+            return None;
+        }
+        offset += size;
+    }
+
+    None
+}
+
+fn validate_encoder_params(params: &str) -> Result<(), String> {
+    let valid = !params.is_empty()
+        && !params.chars().any(char::is_whitespace)
+        && params.split(':').all(|pair| match pair.split_once('=') {
+            Some((key, value)) => {
+                !key.is_empty()
+                    && !value.is_empty()
+                    && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            }
+            None => false,
+        });
+    if valid {
+        Ok(())
     } else {
-        let mut filters: Vec<String> = Vec::new();
-        if let (Some(w), Some(h)) = (options.width, options.height) {
-            filters.push(format!("scale={w}:{h}"));
+        Err(format!("Encoder params must look like key=value:key=value, got: {params}"))
+    }
+}
+
+/// Trim range in samples for sample-accurate cuts, or None to fall back to time-based seeking.
+/// Only lossless audio-only outputs qualify, a lossy encoder smears the cut anyway.
+fn sample_trim_range(options: &ConversionOptions) -> Option<(u64, u64)> {
+    let lossless = matches!(options.format.as_deref(), Some("flac") | Some("wav"));
+    if !options.sample_accurate || !options.is_audio_only || !lossless || options.copy_streams {
+        return None;
+    }
+    let rate = options.source_sample_rate? as u64;
+    let trimmed_ms = options.end_ms.saturating_sub(options.start_ms);
+    let length_ms = match output_length_cap(options) {
+        Some(cap) => trimmed_ms.min((cap * 1000.0).round() as u64),
+        None => trimmed_ms,
+    };
+    // integer math so 44.1k cuts don't pick up float rounding
+    let start = options.start_ms * rate / 1000;
+    let end = (options.start_ms + length_ms) * rate / 1000;
+    Some((start, end))
+}
+
+fn push_filter_complex(args: &mut Vec<String>, options: &ConversionOptions) {
+    if let Some(ref graph) = options.filter_complex {
+        args.push("-filter_complex".to_string());
+        args.push(graph.clone());
+    }
+    for map in options.maps.iter().flatten() {
+        args.push("-map".to_string());
+        args.push(map.trim().to_string());
+    }
+}
+
+/// How far before start_ms a re-encoded trim seeks on the input, with the output -ss cutting the
+/// difference. Transcoding input seeks are already frame accurate in most containers, but ones with
+/// unreliable indexes (mpeg-ts, open-GOP captures) land a few frames off; decoding a short run-up
+/// makes the cut exact either way without decoding from the top. None when a plain seek is used.
+fn hybrid_preroll_ms(options: &ConversionOptions, format: &str) -> Option<u64> {
+    let excluded = options.start_ms == 0
+        || options.copy_streams
+        || options.copy_video
+        || options.precise_seek
+        || options.still_image
+        || options.reverse
+        // output -ss and drawtext's pts both see the filtered timeline
+        || options.speed.is_some()
+        || options.timecode_overlay
+        || options.filter_complex.is_some()
+        || sample_trim_range(options).is_some()
+        // an attached cover is a single frame at 0, the output -ss would throw it away
+        || (options.cover_image.is_some() && options.audio_video_mode.as_deref() != Some("cover"))
+        || uses_offset_audio_input(options, format);
+    if excluded {
+        return None;
+    }
+    let preroll = options.seek_preroll_ms.unwrap_or(DEFAULT_SEEK_PREROLL_MS).min(options.start_ms);
+    (preroll > 0).then_some(preroll)
+}
+
+const DEFAULT_SEEK_PREROLL_MS: u64 = 2000;
+
+fn uses_offset_audio_input(options: &ConversionOptions, format: &str) -> bool {
+    !options.is_audio_only && format != "gif" && options.audio_offset_ms.is_some_and(|o| o > 0)
+}
+
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 4.0;
+
+// A 1080p frame is ~3MB raw, so 30s at 30fps already needs close to 3GB while reversing.
+const MAX_REVERSE_SECS: u64 = 30;
+
+/// Length of the file ffmpeg will write, in seconds: the trimmed range adjusted for speed,
+/// capped by the preview length or max_duration_secs when set.
+fn expected_output_duration(options: &ConversionOptions) -> f64 {
+    let duration = uncapped_output_duration(options);
+    match output_length_cap(options) {
+        Some(cap) => duration.min(cap),
+        None => duration,
+    }
+}
+
+fn uncapped_output_duration(options: &ConversionOptions) -> f64 {
+    let trimmed = options.end_ms.saturating_sub(options.start_ms) as f64 / 1000.0;
+    trimmed / options.speed.unwrap_or(1.0)
+}
+
+fn output_length_cap(options: &ConversionOptions) -> Option<f64> {
+    match (options.preview_duration_secs, options.max_duration_secs) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn truncation_warning(options: &ConversionOptions) -> Option<String> {
+    let max = options.max_duration_secs?;
+    let full = uncapped_output_duration(options);
+    (full > max).then(|| format!("Output was cut to the {max}s limit, the selection runs {full:.1}s"))
+}
+
+// notes are the builder's adjustments, they go after the truncation warning
+fn conversion_result(options: &ConversionOptions, stats: &FfmpegStats, notes: &[String]) -> ConversionResult {
+    let truncation = truncation_warning(options);
+    let truncated = truncation.is_some();
+    let warnings: Vec<String> = truncation.into_iter().chain(notes.iter().cloned()).collect();
+    ConversionResult {
+        output_path: options.output_path.clone(),
+        duration_seconds: expected_output_duration(options),
+        dropped_frames: stats.dropped_frames,
+        duplicated_frames: stats.duplicated_frames,
+        truncated,
+        warning: (!warnings.is_empty()).then(|| warnings.join("; ")),
+    }
+}
+
+/// Maps a ProRes profile name to prores_ks's numeric profile and the pixel format it needs.
+/// Defaults to HQ, which is what the app always used before profiles were selectable.
+fn prores_profile(name: Option<&str>) -> Result<(u8, &'static str), String> {
+    match name.unwrap_or("hq") {
+        "proxy" => Ok((0, "yuv422p10le")),
+        "lt" => Ok((1, "yuv422p10le")),
+        "standard" => Ok((2, "yuv422p10le")),
+        "hq" => Ok((3, "yuv422p10le")),
+        "4444" => Ok((4, "yuva444p10le")),
+        "4444xq" => Ok((5, "yuva444p10le")),
+        other => Err(format!(
+            "Unsupported ProRes profile: {other} (use proxy, lt, standard, hq, 4444 or 4444xq)"
+        )),
+    }
+}
+
+fn dnx_pix_fmt(profile: &str) -> Result<&'static str, String> {
+    match profile {
+        "dnxhd" | "dnxhr_lb" | "dnxhr_sq" | "dnxhr_hq" => Ok("yuv422p"),
+        "dnxhr_hqx" => Ok("yuv422p10le"),
+        "dnxhr_444" => Ok("yuv444p10le"),
+        other => Err(format!(
+            "Unsupported DNx profile: {other} (use dnxhd, dnxhr_lb, dnxhr_sq, dnxhr_hq, dnxhr_hqx or dnxhr_444)"
+        )),
+    }
+}
+
+// Legacy DNxHD only encodes these exact size/frame rate/bitrate (Mbps) combinations (8-bit).
+const DNXHD_COMBOS: &[(u32, u32, f64, &[u64])] = &[
+    (1920, 1080, 23.976, &[36, 115, 175]),
+    (1920, 1080, 24.0, &[36, 115, 175]),
+    (1920, 1080, 25.0, &[36, 120, 185]),
+    (1920, 1080, 29.97, &[45, 145, 220]),
+    (1920, 1080, 50.0, &[75, 240, 365]),
+    (1920, 1080, 59.94, &[90, 290, 440]),
+    (1280, 720, 23.976, &[60, 90]),
+    (1280, 720, 25.0, &[60, 90]),
+    (1280, 720, 29.97, &[75, 110]),
+    (1280, 720, 50.0, &[120, 185]),
+    (1280, 720, 59.94, &[145, 220]),
+];
+
+fn validate_dnxhd_combo(options: &ConversionOptions) -> Result<(), String> {
+    let (Some(w), Some(h), Some(fps), Some(kbps)) =
+        (options.width, options.height, options.fps, options.video_bitrate_kbps)
+    else {
+        return Err("DNxHD needs an explicit resolution, fps and bitrate; use a dnxhr_* profile to encode any size".to_string());
+    };
+
+    let sizes: Vec<&(u32, u32, f64, &[u64])> = DNXHD_COMBOS
+        .iter()
+        .filter(|(cw, ch, _, _)| *cw == w && *ch == h)
+        .collect();
+    if sizes.is_empty() {
+        return Err(format!("DNxHD only supports 1920x1080 and 1280x720, got {w}x{h}; use a dnxhr_* profile instead"));
+    }
+    let Some((_, _, _, rates)) = sizes.iter().find(|(_, _, cfps, _)| (cfps - fps).abs() < 0.01) else {
+        let valid: Vec<String> = sizes.iter().map(|(_, _, f, _)| f.to_string()).collect();
+        return Err(format!("DNxHD at {w}x{h} supports {} fps, got {fps}", valid.join(", ")));
+    };
+    if !rates.iter().any(|mbps| mbps * 1000 == kbps) {
+        let valid: Vec<String> = rates.iter().map(|r| format!("{r}M")).collect();
+        return Err(format!("DNxHD at {w}x{h} {fps}fps supports bitrates {}", valid.join(", ")));
+    }
+    Ok(())
+}
+
+/// "frame" keeps the source frame rate (50i -> 25p). "field" outputs one frame per field
+/// (50i -> 50p), which keeps motion smooth; the duration doesn't change, so -t stays valid,
+/// and an explicit fps still wins because the fps filter runs after yadif.
+fn deinterlace_filter(mode: &str) -> Result<&'static str, String> {
+    match mode {
+        "frame" => Ok("yadif=0"),
+        "field" => Ok("yadif=1"),
+        other => Err(format!("Unsupported deinterlace mode: {other} (use frame or field)")),
+    }
+}
+
+/// Burns the running output timestamp into the frame with drawtext.
+fn build_timecode_filter(options: &ConversionOptions) -> Result<String, String> {
+    let (x, y) = match options.timecode_position.as_deref().unwrap_or("top-left") {
+        "top-left" => ("10", "10"),
+        "top-right" => ("w-tw-10", "10"),
+        "bottom-left" => ("10", "h-th-10"),
+        "bottom-right" => ("w-tw-10", "h-th-10"),
+        other => return Err(format!("Unsupported timecode position: {other}")),
+    };
+    let size = options.timecode_font_size.unwrap_or(24);
+    if !(6..=200).contains(&size) {
+        return Err(format!("Timecode font size must be between 6 and 200, got {size}"));
+    }
+
+    let mut filter = format!(
+        "drawtext=text='%{{pts\\:hms}}':x={x}:y={y}:fontsize={size}:fontcolor=white:box=1:boxcolor=black@0.5"
+    );
+    if let Some(ref font) = options.timecode_font_file {
+        if !std::path::Path::new(font).is_file() {
+            return Err(format!("Font file not found: {font}"));
         }
-        if let Some(fps) = options.fps {
-            filters.push(format!("fps={fps}"));
+        filter.push_str(&format!(":fontfile='{}'", escape_filter_path(font)?));
+    }
+    Ok(filter)
+}
+
+// Paths inside a filter graph need ':' escaped, and forward slashes sidestep Windows backslashes.
+fn escape_filter_path(path: &str) -> Result<String, String> {
+    if path.contains('\'') {
+        return Err(format!("Paths used in filters can't contain quotes: {path}"));
+    }
+    Ok(path.replace('\\', "/").replace(':', "\\:"))
+}
+
+const GIF_DITHER_MODES: [&str; 5] = ["none", "bayer", "floyd_steinberg", "sierra2", "sierra2_4a"];
+
+/// Two-stage palette pipeline: build an optimal palette from the clip itself, then map frames onto it.
+/// Fewer colors with bayer dithering gives much smaller files.
+fn build_gif_palette_filter(options: &ConversionOptions) -> Result<String, String> {
+    let max_colors = options.gif_max_colors.unwrap_or(256);
+    if !(2..=256).contains(&max_colors) {
+        return Err(format!("GIF colors must be between 2 and 256, got {max_colors}"));
+    }
+    let dither = options.gif_dither.as_deref().unwrap_or("sierra2_4a");
+    if !GIF_DITHER_MODES.contains(&dither) {
+        return Err(format!("Unsupported GIF dither mode: {dither}"));
+    }
+    Ok(format!(
+        "split[pa][pb];[pa]palettegen=max_colors={max_colors}[pal];[pb][pal]paletteuse=dither={dither}"
+    ))
+}
+
+// Integer math only, so 1234ms is always "00:00:01.234" and never drifts to .233 through f64 rounding.
+fn format_timestamp(ms: u64) -> String {
+    let hours = ms / 3_600_000;
+    let minutes = (ms % 3_600_000) / 60_000;
+    let seconds = (ms % 60_000) / 1000;
+    let millis = ms % 1000;
+    format!("{hours:02}:{minutes:02}:{seconds:02}.{millis:03}")
+}
+
+/// Parses `HH:MM:SS.mmm`, `MM:SS` or `SS.mmm` into milliseconds; the inverse of format_timestamp.
+fn parse_timestamp(text: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid timestamp: {text:?}, expected HH:MM:SS.mmm, MM:SS or SS.mmm");
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+    let (whole, rest) = parts.split_last().ok_or_else(invalid)?;
+
+    let (secs, frac) = whole.split_once('.').unwrap_or((whole, ""));
+    if secs.is_empty() || !secs.bytes().all(|b| b.is_ascii_digit()) || frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let seconds: u64 = secs.parse().map_err(|_| invalid())?;
+    // "1.5" is 1500ms, not 1005ms
+    let millis: u64 = format!("{frac:0<3}").parse().map_err(|_| invalid())?;
+    // seconds only go past 59 when they're the biggest unit given
+    if !rest.is_empty() && seconds >= 60 {
+        return Err(invalid());
+    }
+
+    let mut total = seconds * 1000 + millis;
+    for (i, unit) in rest.iter().rev().enumerate() {
+        if unit.is_empty() || !unit.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
         }
-        if !filters.is_empty() {
-            args.push("-vf".to_string());
-            args.push(filters.join(","));
+        let value: u64 = unit.parse().map_err(|_| invalid())?;
+        let is_minutes_below_hours = i == 0 && rest.len() == 2;
+        if is_minutes_below_hours && value >= 60 {
+            return Err(invalid());
         }
+        total += value * if i == 0 { 60_000 } else { 3_600_000 };
+    }
+    Ok(total)
+}
 
-        let allowed_video = video_codecs_for_format(format);
-        if allowed_video.is_empty() {
-            return Err(format!("No video codecs available for format: {format}"));
-        }
-        let mut video_codec = if let Some(ref user) = options.video_codec {
-            if allowed_video.iter().any(|c| c == user) {
-                user.as_str()
-            } else {
-                return Err(format!("Video codec {user} not allowed for format {format}"));
-            }
-        } else {
-            allowed_video[0]
-        };
+// Folds start/end strings into start_ms/end_ms once, up front. They're cleared afterwards so a
+// resumed job (which moves start_ms forward) isn't dragged back to the original start.
+fn resolve_timestamp_strings(options: &mut ConversionOptions) -> Result<(), String> {
+    if let Some(start) = options.start.take() {
+        options.start_ms = parse_timestamp(&start)?;
+    }
+    if let Some(end) = options.end.take() {
+        options.end_ms = parse_timestamp(&end)?;
+    }
+    Ok(())
+}
 
-        let mut audio_codec: Option<&str>;
-        let mut add_x264_preset = true;
-        let mut pix_fmt: Option<&str> = Some("yuv420p");
-        let mut extra: Vec<String> = Vec::new();
+// Shared by the audio-only and muxed paths: codec, rate control, then the audio filter chain.
+fn build_audio_args(
+    audio_codec: &str,
+    format: &str,
+    options: &ConversionOptions,
+    notes: &mut Vec<String>,
+) -> Result<Vec<String>, String> {
+    let mut args: Vec<String> = Vec::new();
+    let is_aac = audio_codec == "aac";
+    if !is_aac && (options.aac_profile.is_some() || options.aac_vbr.is_some()) {
+        return Err(format!("AAC profile/VBR options need the aac codec, not {audio_codec}"));
+    }
 
-        let allowed_audio = audio_codecs_for_format(format);
-        audio_codec = if let Some(ref user) = options.audio_codec {
-            if allowed_audio.iter().any(|c| c == user) {
-                Some(user.as_str())
-            } else if !allowed_audio.is_empty() {
-                Some(allowed_audio[0])
-            } else {
-                None
+    let mut codec = audio_codec;
+    let mut profile: Option<&str> = None;
+    if let Some(ref p) = options.aac_profile {
+        match p.as_str() {
+            "aac_low" => profile = Some("aac_low"),
+            // ffmpeg's native encoder has no SBR/PS support, so HE profiles go through libfdk_aac
+            "aac_he" | "aac_he_v2" => {
+                codec = "libfdk_aac";
+                profile = Some(p.as_str());
             }
-        } else {
-            allowed_audio.first().copied()
-        };
-
-        if video_codec == "prores_ks" {
-            pix_fmt = Some("yuv422p10le");
-            extra.push("-profile:v".to_string());
-            extra.push("3".to_string()); // standard quality
-        } else if video_codec == "mjpeg" {
-            pix_fmt = Some("yuvj422p");
+            other => return Err(format!("Unsupported AAC profile: {other} (use aac_low, aac_he or aac_he_v2)")),
         }
+    }
 
-        match format {
-            "mp4" | "mov" => {
-                extra.push("-movflags".to_string());
-                extra.push("+faststart".to_string());
-            }
-            "mkv" => {}
-            "webm" => {
-                video_codec = "libvpx-vp9";
-                audio_codec = Some("libopus");
-                add_x264_preset = false;
-            }
-            "avi" => {
-                audio_codec = Some("mp3");
-            }
-            "flv" => {
-                audio_codec = Some("aac");
+    args.push("-c:a".to_string());
+    args.push(codec.to_string());
+    if codec == "libopus" && format == "mp4" {
+        // builds before 4.3 still flag opus-in-mp4 as experimental; newer ones ignore this
+        args.push("-strict".to_string());
+        args.push("-2".to_string());
+    }
+    if let Some(rate) = options.sample_rate {
+        if !(8000..=384_000).contains(&rate) {
+            return Err(format!("Sample rate must be between 8000 and 384000, got {rate}"));
+        }
+        args.push("-ar".to_string());
+        args.push(rate.to_string());
+    }
+    if let Some(channels) = options.channels {
+        if options.downmix.is_some() {
+            return Err("Set either a channel count or a downmix, not both".to_string());
+        }
+        if !(1..=8).contains(&channels) {
+            return Err(format!("Channel count must be between 1 and 8, got {channels}"));
+        }
+        args.push("-ac".to_string());
+        args.push(channels.to_string());
+    }
+    if let Some(cutoff) = options.audio_cutoff_hz {
+        match codec {
+            // libopus only takes its fixed bandwidths and always runs at 48k
+            "libopus" if ![4000, 6000, 8000, 12000, 20000].contains(&cutoff) => {
+                return Err(format!("Opus cutoff must be 4000, 6000, 8000, 12000 or 20000 Hz, got {cutoff}"));
             }
-            "gif" => {
-                video_codec = "gif";
-                audio_codec = None;
-                add_x264_preset = false;
-                pix_fmt = Some("rgb8");
-                extra.push("-an".to_string());
-                extra.push("-loop".to_string());
-                extra.push("0".to_string());
+            "libopus" => {}
+            "aac" | "libfdk_aac" => {
+                let rate = options.sample_rate.or(options.source_sample_rate).unwrap_or(48_000);
+                if cutoff == 0 || cutoff > rate / 2 {
+                    return Err(format!("Cutoff must be between 1 and {} Hz at {rate} Hz, got {cutoff}", rate / 2));
+                }
             }
-            other => return Err(format!("Unsupported format: {other}")),
+            other => return Err(format!("Cutoff only applies to aac and opus, not {other}")),
         }
+        args.push("-cutoff".to_string());
+        args.push(cutoff.to_string());
+    }
+    if let Some(p) = profile {
+        args.push("-profile:a".to_string());
+        args.push(p.to_string());
+    }
+    if profile == Some("aac_he_v2") {
+        // parametric stereo only exists for stereo signals
+        args.push("-ac".to_string());
+        args.push("2".to_string());
+    }
 
-        args.push("-c:v".to_string());
-        args.push(video_codec.to_string());
-        if add_x264_preset && video_codec == "libx264" {
-            args.push("-preset".to_string());
-            args.push("medium".to_string());
+    args.extend(build_audio_rate_args(codec, options, notes)?);
+
+    let audio_filters = build_audio_filters(options)?;
+    // -af can't touch a graph output, but the visualizer graph leaves the audio as a plain map
+    let audio_mapped_directly =
+        options.filter_complex.is_none() || matches!(options.audio_video_mode.as_deref(), Some("waveform" | "spectrum"));
+    if audio_mapped_directly && !audio_filters.is_empty() {
+        args.push("-af".to_string());
+        args.push(audio_filters.join(","));
+    }
+
+    Ok(args)
+}
+
+/// Bitrate/quality flags for the chosen rate mode. Each encoder spells these differently, and
+/// several only approximate a mode (native aac has no true CBR, fdk has no ABR).
+fn build_audio_rate_args(codec: &str, options: &ConversionOptions, notes: &mut Vec<String>) -> Result<Vec<String>, String> {
+    let mode = match (options.audio_rate_mode.as_deref(), options.aac_vbr) {
+        (None, Some(_)) => Some("vbr"),
+        (Some(mode), Some(_)) if mode != "vbr" => {
+            return Err(format!("AAC VBR quality only applies in vbr mode, not {mode}"));
+        }
+        (mode, _) => mode,
+    };
+    if let Some(mode) = mode {
+        if !matches!(mode, "cbr" | "abr" | "vbr") {
+            return Err(format!("Unsupported audio rate mode: {mode} (use cbr, abr or vbr)"));
+        }
+        // only the lossy encoders have a ceiling, lossless and pcm ones take no rate at all
+        if max_audio_bitrate_kbps(codec).is_none() {
+            return Err(format!("{codec} has no bitrate modes"));
+        }
+    }
+    let bitrate = options.audio_bitrate_kbps.map(|requested| match max_audio_bitrate_kbps(codec) {
+        Some(max) if requested > max => {
+            notes.push(format!("Clamped {codec} audio bitrate from {requested}k to {max}k"));
+            max
         }
+        _ => requested,
+    });
+    let needs_bitrate = || bitrate.ok_or_else(|| format!("{} mode needs an audio bitrate", mode.unwrap_or_default()));
 
-        if let Some(vb) = options.video_bitrate_kbps {
-            // Skip setting a bitrate for GIF; the encoder will choose based on palette.
-            if video_codec != "gif" {
-                args.push("-b:v".to_string());
-                args.push(format!("{vb}k"));
+    let mut args: Vec<String> = Vec::new();
+    match (mode, codec) {
+        (Some("vbr"), "aac" | "libfdk_aac") => {
+            let q = options.aac_vbr.unwrap_or(3);
+            if !(1..=5).contains(&q) {
+                return Err(format!("AAC VBR quality must be between 1 and 5, got {q}"));
+            }
+            if codec == "libfdk_aac" {
+                args.push("-vbr".to_string());
+                args.push(q.to_string());
+            } else {
+                // native aac takes a 0.1-2 quality scale; spread the 1-5 levels across it
+                args.push("-q:a".to_string());
+                args.push(format!("{:.1}", q as f64 * 0.4));
             }
         }
-
-        if let Some(ac) = audio_codec {
-            args.push("-c:a".to_string());
-            args.push(ac.to_string());
-            if let Some(ab) = options.audio_bitrate_kbps {
+        (Some("vbr"), "libmp3lame") => {
+            // closest LAME -V level for the requested average, V2 (~190k) without one
+            let level = match bitrate.unwrap_or(190) {
+                b if b >= 225 => 0,
+                b if b >= 175 => 2,
+                b if b >= 145 => 4,
+                b if b >= 115 => 6,
+                _ => 8,
+            };
+            args.push("-q:a".to_string());
+            args.push(level.to_string());
+        }
+        (Some(mode), "libopus") => {
+            args.push("-b:a".to_string());
+            args.push(format!("{}k", needs_bitrate()?));
+            args.push("-vbr".to_string());
+            args.push(match mode { "cbr" => "off", "abr" => "constrained", _ => "on" }.to_string());
+        }
+        (Some("abr"), "aac") => {
+            // twoloop spends the bits more carefully than the default fast coder, worth it for speech
+            args.push("-b:a".to_string());
+            args.push(format!("{}k", needs_bitrate()?));
+            args.push("-aac_coder".to_string());
+            args.push("twoloop".to_string());
+        }
+        (Some("abr"), "libmp3lame") => {
+            args.push("-b:a".to_string());
+            args.push(format!("{}k", needs_bitrate()?));
+            args.push("-abr".to_string());
+            args.push("1".to_string());
+        }
+        (Some("cbr"), "libvorbis") => {
+            let b = needs_bitrate()?;
+            args.extend(["-b:a", "-minrate", "-maxrate"].iter().flat_map(|flag| [flag.to_string(), format!("{b}k")]));
+        }
+        (Some(_), _) => {
+            args.push("-b:a".to_string());
+            args.push(format!("{}k", needs_bitrate()?));
+        }
+        (None, _) => {
+            if let Some(b) = bitrate {
                 args.push("-b:a".to_string());
-                args.push(format!("{ab}k"));
+                args.push(format!("{b}k"));
             }
         }
+    }
+    Ok(args)
+}
+
+fn build_audio_filters(options: &ConversionOptions) -> Result<Vec<String>, String> {
+    let mut filters: Vec<String> = Vec::new();
+
+    if let Some((start, end)) = sample_trim_range(options) {
+        filters.push(format!("atrim=start_sample={start}:end_sample={end}"));
+        filters.push("asetpts=PTS-STARTPTS".to_string());
+    }
+
+    match options.audio_offset_ms {
+        Some(offset) if offset < 0 => {
+            let secs = offset.unsigned_abs() as f64 / 1000.0;
+            filters.push(format!("atrim=start={secs:.3}"));
+            filters.push("asetpts=PTS-STARTPTS".to_string());
+        }
+        // muxed outputs delay through a shifted second input, audio-only outputs just pad the start
+        Some(offset) if offset > 0 && options.is_audio_only => {
+            filters.push(format!("adelay=delays={offset}:all=1"));
+        }
+        _ => {}
+    }
 
-        args.extend(extra);
+    if options.reverse {
+        filters.push("areverse".to_string());
+    }
 
-        if let Some(fmt) = pix_fmt {
-            args.push("-pix_fmt".to_string());
-            args.push(fmt.to_string());
+    if let Some(speed) = options.speed {
+        // atempo only accepts 0.5-2.0 per instance, so chain it for bigger changes
+        let mut remaining = speed;
+        while remaining > 2.0 {
+            filters.push("atempo=2.0".to_string());
+            remaining /= 2.0;
+        }
+        while remaining < 0.5 {
+            filters.push("atempo=0.5".to_string());
+            remaining /= 0.5;
         }
+        filters.push(format!("atempo={remaining}"));
     }
 
-    args.push(options.output_path.clone());
-    Ok(args)
+    if let Some(ref mode) = options.downmix {
+        // plain -ac drops or mis-weights the center channel on some builds, so fold it in explicitly.
+        // '<' renormalizes the gains, and channels missing from the source are ignored by pan.
+        let pan = match mode.as_str() {
+            "stereo" => "pan=stereo|FL<FL+0.707*FC+0.707*BL+0.707*SL|FR<FR+0.707*FC+0.707*BR+0.707*SR",
+            "mono" => "pan=mono|c0<0.707*FL+0.707*FR+FC+0.5*BL+0.5*BR+0.5*SL+0.5*SR",
+            other => return Err(format!("Unsupported downmix mode: {other}")),
+        };
+        filters.push(pan.to_string());
+    }
+
+    // after the downmix so sofalizer/crossfeed see the final channel layout
+    if let Some(ref mode) = options.spatial_audio {
+        let filter = match (mode.as_str(), options.spatial_sofa_file.as_deref()) {
+            ("binaural", Some(sofa)) => format!("sofalizer=sofa='{}':type=freq", escape_filter_path(sofa)?),
+            ("binaural", None) | ("crossfeed", _) => "crossfeed".to_string(),
+            ("widen", _) => "extrastereo=m=1.5".to_string(),
+            (other, _) => return Err(format!("Unsupported spatial audio mode: {other}")),
+        };
+        filters.push(filter);
+    }
+
+    Ok(filters)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -397,7 +5861,643 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_store::Builder::default().build())
-        .invoke_handler(tauri::generate_handler![analyze_media, run_conversion])
+        .manage(FfmpegCapabilities::default())
+        .manage(ProbeCache::default())
+        .setup(|app| {
+            app.manage(WorkDir(RwLock::new(load_work_dir(app))));
+            app.manage(DefaultPreset(RwLock::new(load_default_preset(app))));
+            app.manage(SafeModeRoot(RwLock::new(load_safe_mode_root(app))));
+            app.manage(ConversionSlots(RwLock::new(Arc::new(Semaphore::new(load_max_conversions(app))))));
+
+            // warm the capability lists in the background so the first conversion doesn't pay for it
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                let capabilities = handle.state::<FfmpegCapabilities>();
+                capabilities.version();
+                capabilities.encoders();
+                capabilities.filters();
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            analyze_media,
+            read_tags,
+            run_conversion,
+            trim_multiple,
+            convert_with_master,
+            export_chapters,
+            list_attachments,
+            estimate_output_size,
+            rotate_metadata_only,
+            split_channels,
+            validate_options,
+            clear_probe_cache,
+            generate_sprite_sheet,
+            set_safe_mode_root,
+            generate_thumbnails,
+            set_default_preset,
+            convert_multi_format,
+            make_proxy,
+            make_visualizer_video,
+            make_hover_preview,
+            codec_benchmark,
+            resume_conversion,
+            detect_crop,
+            convert_to_dash,
+            mux_tracks,
+            repair_container,
+            analyze_audio_loudness,
+            suggest_output_path,
+            suggest_tagged_output_path,
+            recommend_settings,
+            list_keyframes,
+            compare_quality,
+            get_ffmpeg_capabilities,
+            analyze_directory,
+            set_temp_dir,
+            set_max_concurrent_conversions,
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts() -> ConversionOptions {
+        ConversionOptions {
+            input_path: "in.mp4".to_string(),
+            output_path: "out.mp4".to_string(),
+            end_ms: 10_000,
+            format: Some("mp4".to_string()),
+            ..Default::default()
+        }
+    }
+
+    // the builder checks that side files exist, so hand it a real (empty) one
+    fn temp_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("xhmpeg-test-{name}"));
+        std::fs::write(&path, b"").unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn value_after<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+        args.iter().position(|a| a == flag).map(|i| args[i + 1].as_str())
+    }
+
+    // every -map has to come after the last -i, ffmpeg rejects a map pointing at a later input
+    fn assert_inputs_before_maps(args: &[String]) {
+        let last_input = args.iter().rposition(|a| a == "-i").unwrap();
+        let first_map = args.iter().position(|a| a.starts_with("-map")).unwrap();
+        assert!(last_input < first_map, "{args:?}");
+    }
+
+    #[test]
+    fn offset_audio_maps_come_after_cover_input() {
+        let options = ConversionOptions {
+            audio_offset_ms: Some(500),
+            cover_image: Some(temp_file("cover.jpg")),
+            ..opts()
+        };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert_inputs_before_maps(&args);
+        assert!(args.windows(2).any(|w| w[0] == "-map" && w[1] == "2:v:0"));
+    }
+
+    #[test]
+    fn chapters_input_comes_before_cover_maps() {
+        let options = ConversionOptions {
+            cover_image: Some(temp_file("cover.jpg")),
+            audio_video_mode: Some("cover".to_string()),
+            import_chapters: Some(temp_file("chapters.txt")),
+            ..opts()
+        };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert_inputs_before_maps(&args);
+        let shortest = args.iter().position(|a| a == "-shortest").unwrap();
+        assert!(args.iter().rposition(|a| a == "-i").unwrap() < shortest);
+        assert!(args.windows(2).any(|w| w[0] == "-map_chapters" && w[1] == "2"));
+    }
+
+    // the two -ss values in the order they appear: input side first, then output side
+    fn seeks(args: &[String]) -> Vec<&str> {
+        args.windows(2).filter(|w| w[0] == "-ss").map(|w| w[1].as_str()).collect()
+    }
+
+    #[test]
+    fn reencoded_trim_seeks_before_start_and_cuts_on_output() {
+        let options = ConversionOptions { start_ms: 5000, ..opts() };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert_eq!(seeks(&args), ["00:00:03.000", "00:00:02.000"]);
+        let input = args.iter().position(|a| a == "-i").unwrap();
+        assert!(args.iter().position(|a| a == "-ss").unwrap() < input);
+        assert!(args.iter().rposition(|a| a == "-ss").unwrap() > input);
+    }
+
+    #[test]
+    fn preroll_is_capped_by_start() {
+        // a start inside the first preroll decodes from the top and cuts it all on the output
+        let options = ConversionOptions { start_ms: 500, ..opts() };
+        assert_eq!(seeks(&build_ffmpeg_args(&options).unwrap()), ["00:00:00.500"]);
+
+        let options = ConversionOptions { start_ms: 5000, seek_preroll_ms: Some(0), ..opts() };
+        assert_eq!(seeks(&build_ffmpeg_args(&options).unwrap()), ["00:00:05.000"]);
+    }
+
+    #[test]
+    fn sample_accurate_reverse_has_no_input_duration() {
+        let options = ConversionOptions {
+            output_path: "out.flac".to_string(),
+            start_ms: 5000,
+            end_ms: 8000,
+            format: Some("flac".to_string()),
+            is_audio_only: true,
+            sample_accurate: true,
+            source_sample_rate: Some(48000),
+            reverse: true,
+            ..opts()
+        };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert!(!args.iter().any(|a| a == "-t" || a == "-ss"), "{args:?}");
+        let af = value_after(&args, "-af").unwrap();
+        assert!(af.starts_with("atrim=start_sample=240000:end_sample=384000,"), "{af}");
+        assert!(af.contains("areverse"), "{af}");
+    }
+
+    #[test]
+    fn waveform_mode_keeps_audio_filters() {
+        let options = ConversionOptions {
+            audio_video_mode: Some("waveform".to_string()),
+            audio_offset_ms: Some(-250),
+            ..opts()
+        };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert!(value_after(&args, "-filter_complex").unwrap().contains("showwaves"));
+        assert!(value_after(&args, "-af").unwrap().contains("atrim=start=0.250"), "{args:?}");
+    }
+
+    #[test]
+    fn preserved_attachments_copy_subtitles() {
+        let options = ConversionOptions {
+            output_path: "out.mkv".to_string(),
+            format: Some("mkv".to_string()),
+            preserve_attachments: true,
+            ..opts()
+        };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert!(args.windows(2).any(|w| w[0] == "-map" && w[1] == "0:s?"));
+        assert_eq!(value_after(&args, "-c:s"), Some("copy"));
+        assert_eq!(value_after(&args, "-c:t"), Some("copy"));
+    }
+
+    #[test]
+    fn smart_copy_respects_encoder_settings() {
+        let probe = serde_json::json!({"streams": [
+            {"codec_type": "video", "codec_name": "h264"},
+            {"codec_type": "audio", "codec_name": "aac"},
+        ]});
+        let mut options = ConversionOptions { smart_copy: true, ..opts() };
+        apply_smart_copy(&mut options, &probe).unwrap();
+        assert!(options.copy_streams);
+
+        let mut options = ConversionOptions { smart_copy: true, preset: Some("slow".to_string()), ..opts() };
+        apply_smart_copy(&mut options, &probe).unwrap();
+        assert!(!options.copy_streams && !options.copy_video);
+    }
+
+    #[test]
+    fn dash_renditions_share_keyframes() {
+        let info = parse_media_info(serde_json::json!({
+            "format": {"duration": "60.0"},
+            "streams": [{"codec_type": "video", "width": 1920, "height": 1080}],
+        }))
+        .unwrap();
+        let options = DashOptions {
+            input_path: "in.mp4".to_string(),
+            output_dir: "out".to_string(),
+            renditions: [(1280, 720), (640, 360)]
+                .map(|(width, height)| DashRendition {
+                    width,
+                    height,
+                    video_bitrate_kbps: Some(1000),
+                    crf: None,
+                    max_bitrate_kbps: None,
+                })
+                .into(),
+            audio_bitrate_kbps: None,
+            segment_secs: Some(2),
+        };
+        let args = build_dash_args(&options, &info, std::path::Path::new("out/manifest.mpd")).unwrap();
+        for i in 0..2 {
+            assert_eq!(value_after(&args, &format!("-force_key_frames:v:{i}")), Some("expr:gte(t,n_forced*2)"));
+            assert_eq!(value_after(&args, &format!("-sc_threshold:v:{i}")), Some("0"));
+        }
+    }
+
+    #[test]
+    fn validation_leaves_audio_bitrate_to_the_builder() {
+        let options = ConversionOptions {
+            input_path: temp_file("in.mp4"),
+            audio_codec: Some("aac".to_string()),
+            audio_bitrate_kbps: Some(640),
+            ..opts()
+        };
+        let errors = collect_validation_errors(&options);
+        assert!(errors.is_empty(), "{errors:?}");
+    }
+
+    #[test]
+    fn validation_flags_impossible_bitrate_for_duration() {
+        let options = ConversionOptions {
+            input_path: temp_file("in.mp4"),
+            end_ms: 2 * 3600 * 1000,
+            video_bitrate_kbps: Some(5_000_000_000),
+            ..opts()
+        };
+        let errors = collect_validation_errors(&options);
+        assert!(errors.iter().any(|e| e.field == "video_bitrate_kbps"), "{errors:?}");
+    }
+
+    #[test]
+    fn stderr_records_split_on_cr_and_lf() {
+        let mut reader = std::io::Cursor::new(b"Input #0\nframe=1 fps=0\rframe=2 fps=0\rError opening\r\nend".to_vec());
+        let mut records = Vec::new();
+        while let Some(record) = read_stderr_record(&mut reader).unwrap() {
+            records.push(record);
+        }
+        assert_eq!(records, ["Input #0", "frame=1 fps=0", "frame=2 fps=0", "Error opening", "", "end"]);
+    }
+
+    #[test]
+    fn preroll_follows_the_applied_visualizer_graph() {
+        let options = ConversionOptions { start_ms: 5000, audio_video_mode: Some("spectrum".to_string()), ..opts() };
+        assert!(hybrid_preroll_ms(&options, "mp4").is_some());
+        let applied = with_presets_applied(&options).unwrap();
+        assert_eq!(hybrid_preroll_ms(&applied, "mp4"), None);
+        assert_eq!(seeks(&build_ffmpeg_args(&options).unwrap()), ["00:00:05.000"]);
+    }
+
+    #[test]
+    fn safe_mode_rejects_paths_outside_the_root() {
+        let root = std::env::temp_dir().join("xhmpeg-test-root");
+        std::fs::create_dir_all(root.join("clips")).unwrap();
+        std::fs::write(root.join("clips/in.mp4"), b"").unwrap();
+        let root = root.canonicalize().unwrap();
+        let inside = |rel: &str| root.join(rel).to_string_lossy().to_string();
+
+        assert!(ensure_within_root(&inside("clips/in.mp4"), &root, true).is_ok());
+        // outputs don't exist yet, their folder is what gets checked
+        assert!(ensure_within_root(&inside("clips/out.mp4"), &root, false).is_ok());
+        assert!(ensure_within_root(&inside("clips/../../escape.mp4"), &root, false).is_err());
+        assert!(ensure_within_root("clips/in.mp4", &root, true).is_err());
+        assert!(ensure_within_root(&inside("missing/out.mp4"), &root, false).is_err());
+        let outside = std::env::temp_dir().join("xhmpeg-test-outside.mp4");
+        std::fs::write(&outside, b"").unwrap();
+        assert!(ensure_within_root(&outside.to_string_lossy(), &root, true).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn safe_mode_follows_symlinks_out_of_the_root() {
+        let root = std::env::temp_dir().join("xhmpeg-test-link-root");
+        std::fs::create_dir_all(&root).unwrap();
+        let link = root.join("link");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(std::env::temp_dir().canonicalize().unwrap(), &link).unwrap();
+        let root = root.canonicalize().unwrap();
+        let through_link = link.join("out.mp4").to_string_lossy().to_string();
+        assert!(ensure_within_root(&through_link, &root, false).is_err());
+    }
+
+    #[test]
+    fn attached_cover_skips_preroll() {
+        let options = ConversionOptions { start_ms: 5000, cover_image: Some(temp_file("cover.jpg")), ..opts() };
+        assert_eq!(hybrid_preroll_ms(&options, "mp4"), None);
+        assert_eq!(seeks(&build_ffmpeg_args(&options).unwrap()), ["00:00:05.000"]);
+    }
+
+    #[test]
+    fn timestamps_keep_milliseconds() {
+        assert_eq!(format_timestamp(1234), "00:00:01.234");
+        assert_eq!(format_timestamp(3_723_004), "01:02:03.004");
+        assert_eq!(format_timestamp(0), "00:00:00.000");
+        let options = ConversionOptions { start_ms: 1234, seek_preroll_ms: Some(0), ..opts() };
+        assert_eq!(seeks(&build_ffmpeg_args(&options).unwrap()), ["00:00:01.234"]);
+    }
+
+    #[test]
+    fn output_duration_follows_speed() {
+        let at = |speed| ConversionOptions { start_ms: 2000, end_ms: 12_000, speed: Some(speed), ..opts() };
+        assert_eq!(expected_output_duration(&at(2.0)), 5.0);
+        assert_eq!(expected_output_duration(&at(0.5)), 20.0);
+        // -t is measured on the sped up output
+        assert_eq!(value_after(&build_ffmpeg_args(&at(2.0)).unwrap(), "-t"), Some("00:00:05.000"));
+        assert_eq!(value_after(&build_ffmpeg_args(&at(0.5)).unwrap(), "-t"), Some("00:00:20.000"));
+    }
+
+    fn probe(streams: serde_json::Value) -> MediaInfo {
+        parse_media_info(serde_json::json!({"format": {"duration": "10.0"}, "streams": streams})).unwrap()
+    }
+
+    #[test]
+    fn rotated_sources_report_display_size() {
+        let info = probe(serde_json::json!([{
+            "codec_type": "video",
+            "width": 1920,
+            "height": 1080,
+            "side_data_list": [{"rotation": -90}],
+        }]));
+        assert_eq!(info.rotation, Some(90));
+        assert_eq!((info.width, info.height), (Some(1080), Some(1920)));
+
+        let info = probe(serde_json::json!([{
+            "codec_type": "video",
+            "width": 1920,
+            "height": 1080,
+            "tags": {"rotate": "180"},
+        }]));
+        assert_eq!(info.rotation, Some(180));
+        assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+    }
+
+    #[test]
+    fn deinterlace_runs_before_scaling() {
+        let options = ConversionOptions {
+            deinterlace: Some("field".to_string()),
+            width: Some(1280),
+            height: Some(720),
+            ..opts()
+        };
+        assert_eq!(value_after(&build_ffmpeg_args(&options).unwrap(), "-vf"), Some("yadif=1,scale=1280:720"));
+        let options = ConversionOptions { deinterlace: Some("frame".to_string()), ..opts() };
+        assert_eq!(value_after(&build_ffmpeg_args(&options).unwrap(), "-vf"), Some("yadif=0"));
+        let options = ConversionOptions { deinterlace: Some("bob".to_string()), ..opts() };
+        assert!(build_ffmpeg_args(&options).is_err());
+    }
+
+    #[test]
+    fn positive_audio_offset_reopens_the_input() {
+        let options = ConversionOptions { start_ms: 3000, audio_offset_ms: Some(500), ..opts() };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert_eq!(value_after(&args, "-itsoffset"), Some("00:00:00.500"));
+        assert_eq!(args.iter().filter(|a| *a == "-i").count(), 2);
+        assert!(args.windows(2).any(|w| w[0] == "-map" && w[1] == "1:a:0?"));
+    }
+
+    #[test]
+    fn negative_audio_offset_trims_the_audio() {
+        let options = ConversionOptions { audio_offset_ms: Some(-250), ..opts() };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert!(!args.iter().any(|a| a == "-itsoffset"));
+        assert!(value_after(&args, "-af").unwrap().contains("atrim=start=0.250"));
+    }
+
+    #[test]
+    fn timestamp_strings_parse() {
+        assert_eq!(parse_timestamp("01:02:03.5"), Ok(3_723_500));
+        assert_eq!(parse_timestamp("02:30"), Ok(150_000));
+        assert_eq!(parse_timestamp("90.25"), Ok(90_250));
+        assert_eq!(parse_timestamp(" 0:00:01.234 "), Ok(1234));
+        for bad in ["", "1:60", "1:60:00", "1.2345", "1:2:3:4", "-5", "a:00", "1::2"] {
+            assert!(parse_timestamp(bad).is_err(), "{bad:?}");
+        }
+        assert_eq!(parse_timestamp(&format_timestamp(3_723_004)), Ok(3_723_004));
+    }
+
+    #[test]
+    fn audio_codec_follows_the_container() {
+        for (format, codec) in [("avi", "libmp3lame"), ("flv", "aac"), ("webm", "libopus"), ("mov", "aac")] {
+            let options = ConversionOptions {
+                output_path: format!("out.{format}"),
+                format: Some(format.to_string()),
+                ..opts()
+            };
+            assert_eq!(value_after(&build_ffmpeg_args(&options).unwrap(), "-c:a"), Some(codec), "{format}");
+        }
+        let options = ConversionOptions {
+            output_path: "out.avi".to_string(),
+            format: Some("avi".to_string()),
+            audio_codec: Some("aac".to_string()),
+            ..opts()
+        };
+        // the builder falls back to what the container takes, validation is what flags the pick
+        assert_eq!(value_after(&build_ffmpeg_args(&options).unwrap(), "-c:a"), Some("libmp3lame"));
+        let options = ConversionOptions { input_path: temp_file("in.mp4"), ..options };
+        assert!(collect_validation_errors(&options).iter().any(|e| e.field == "audio_codec"));
+    }
+
+    #[test]
+    fn strip_metadata_writes_no_tags() {
+        let options = ConversionOptions {
+            strip_metadata: true,
+            extra_metadata: vec![("REPLAYGAIN_TRACK_GAIN".to_string(), "-3.00 dB".to_string())],
+            ..opts()
+        };
+        for options in [options.clone(), ConversionOptions { copy_streams: true, ..options }] {
+            let args = build_ffmpeg_args(&options).unwrap();
+            assert!(!args.iter().any(|a| a == "-metadata"), "{args:?}");
+            assert_eq!(value_after(&args, "-map_metadata"), Some("-1"));
+            assert_eq!(value_after(&args, "-map_chapters"), Some("-1"));
+            assert_eq!(value_after(&args, "-fflags"), Some("+bitexact"));
+        }
+    }
+
+    #[test]
+    fn seek_and_end_modes_combine() {
+        let cases = [
+            (false, "duration", vec!["00:00:03.000", "00:00:02.000"], "-t", "00:00:03.000"),
+            // the output timeline starts at the pre-roll, so -to counts from there
+            (false, "absolute", vec!["00:00:03.000", "00:00:02.000"], "-to", "00:00:05.000"),
+            (true, "duration", vec!["00:00:05.000"], "-t", "00:00:03.000"),
+            (true, "absolute", vec!["00:00:05.000"], "-to", "00:00:08.000"),
+        ];
+        for (precise_seek, end_mode, expected_seeks, end_flag, end) in cases {
+            let options = ConversionOptions {
+                start_ms: 5000,
+                end_ms: 8000,
+                precise_seek,
+                end_mode: Some(end_mode.to_string()),
+                ..opts()
+            };
+            let args = build_ffmpeg_args(&options).unwrap();
+            assert_eq!(seeks(&args), expected_seeks, "{precise_seek} {end_mode}");
+            assert_eq!(value_after(&args, end_flag), Some(end), "{precise_seek} {end_mode}");
+            let other = if end_flag == "-t" { "-to" } else { "-t" };
+            assert!(!args.iter().any(|a| a == other), "{args:?}");
+        }
+        // precise seeks happen on the output, after the input is opened
+        let options = ConversionOptions { start_ms: 5000, end_ms: 8000, precise_seek: true, ..opts() };
+        let args = build_ffmpeg_args(&options).unwrap();
+        assert!(args.iter().position(|a| a == "-ss") > args.iter().position(|a| a == "-i"));
+    }
+
+    #[test]
+    fn empty_or_out_of_source_trims_are_rejected() {
+        let info = probe(serde_json::json!([{"codec_type": "video", "width": 1920, "height": 1080}]));
+        let trim = |start_ms, end_ms| ConversionOptions { start_ms, end_ms, ..opts() };
+        assert!(ensure_trim_within_source(&trim(4000, 4000), &info).is_err());
+        assert!(ensure_trim_within_source(&trim(5000, 4000), &info).is_err());
+        assert!(ensure_trim_within_source(&trim(10_000, 12_000), &info).is_err());
+        assert!(ensure_trim_within_source(&trim(0, 10_000), &info).is_ok());
+        // an end past the source is fine, ffmpeg just stops at the end
+        assert!(ensure_trim_within_source(&trim(9000, 20_000), &info).is_ok());
+        assert!(build_ffmpeg_args(&trim(4000, 4000)).is_err());
+    }
+
+    #[test]
+    fn rotation_and_scale_order() {
+        // scale_first sizes the landscape frame, the turn then makes it portrait
+        let options = ConversionOptions { width: Some(1280), height: Some(720), rotate: Some(90), ..opts() };
+        assert_eq!(value_after(&build_ffmpeg_args(&options).unwrap(), "-vf"), Some("scale=1280:720,transpose=clock"));
+        let options = ConversionOptions {
+            width: Some(720),
+            height: Some(1280),
+            rotate: Some(90),
+            transform_order: Some("rotate_first".to_string()),
+            ..opts()
+        };
+        assert_eq!(value_after(&build_ffmpeg_args(&options).unwrap(), "-vf"), Some("transpose=clock,scale=720:1280"));
+
+        // a percentage of a rotated-first 1920x1080 source is taken from the turned frame
+        let info = probe(serde_json::json!([{"codec_type": "video", "width": 1920, "height": 1080}]));
+        let mut options = ConversionOptions {
+            scale_percent: Some(200.0 / 3.0),
+            rotate: Some(90),
+            transform_order: Some("rotate_first".to_string()),
+            ..opts()
+        };
+        apply_scale_percent(&mut options, 200.0 / 3.0, &info).unwrap();
+        assert_eq!((options.width, options.height), (Some(720), Some(1280)));
+    }
+
+    #[test]
+    fn odd_sizes_round_down_to_even() {
+        let options = ConversionOptions { width: Some(1281), height: Some(721), ..opts() };
+        let mut notes = Vec::new();
+        assert_eq!(value_after(&build_ffmpeg_args_noted(&options, &mut notes).unwrap(), "-vf"), Some("scale=1280:720"));
+        assert_eq!(notes, ["Rounded 1281x721 down to 1280x720, the encoder needs even dimensions"]);
+        // gif has no chroma subsampling to satisfy
+        let options = ConversionOptions {
+            output_path: "out.gif".to_string(),
+            format: Some("gif".to_string()),
+            width: Some(321),
+            height: Some(181),
+            ..opts()
+        };
+        assert!(value_after(&build_ffmpeg_args(&options).unwrap(), "-vf").unwrap().contains("scale=321:181"));
+    }
+
+    #[test]
+    fn cover_art_listed_first_is_not_the_video() {
+        let info = probe(serde_json::json!([
+            {"codec_type": "video", "width": 3000, "height": 3000, "disposition": {"attached_pic": 1}},
+            {"codec_type": "video", "width": 1280, "height": 720, "avg_frame_rate": "30/1"},
+            {"codec_type": "video", "width": 640, "height": 360, "avg_frame_rate": "15/1"},
+            {"codec_type": "audio", "sample_rate": "48000"},
+        ]));
+        assert_eq!((info.width, info.height), (Some(1280), Some(720)));
+        assert_eq!(info.fps, Some(30.0));
+        assert_eq!(info.sample_rate, Some(48000));
+    }
+
+    #[test]
+    fn degenerate_frame_rates_fall_back_to_frame_count() {
+        let info = probe(serde_json::json!([{
+            "codec_type": "video",
+            "width": 1280,
+            "height": 720,
+            "avg_frame_rate": "0/0",
+            "r_frame_rate": "0/1",
+            "nb_frames": "250",
+        }]));
+        assert_eq!(info.fps, Some(25.0));
+
+        let info = probe(serde_json::json!([{
+            "codec_type": "video",
+            "width": 1280,
+            "height": 720,
+            "avg_frame_rate": "N/A",
+            "r_frame_rate": "N/A",
+            "nb_frames": "N/A",
+        }]));
+        assert_eq!(info.fps, None);
+    }
+
+    #[test]
+    fn encoder_params_naming_files_are_found() {
+        let options = ConversionOptions {
+            x264_params: Some("keyint=60:stats=pass.log".to_string()),
+            x265_params: Some("csv=/tmp/x.csv:aq-mode=3".to_string()),
+            muxer_options: Some(HashMap::from([
+                ("movflags".to_string(), "+faststart".to_string()),
+                ("brand".to_string(), "../../etc/passwd".to_string()),
+            ])),
+            ..opts()
+        };
+        let mut found = option_values_naming_files(&options);
+        found.sort();
+        assert_eq!(found, ["../../etc/passwd", "/tmp/x.csv", "pass.log"]);
+    }
+
+    #[test]
+    fn probe_commands_reject_paths_outside_the_root() {
+        use std::future::Future;
+        let root = std::env::temp_dir().join("xhmpeg-test-probe-root");
+        std::fs::create_dir_all(&root).unwrap();
+        let outside = temp_file("outside.mp4");
+        let mut probe = std::pin::pin!(read_tags_within(Some(root.canonicalize().unwrap()), outside));
+        // the check runs before ffprobe is started, so the first poll already has the answer
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        match probe.as_mut().poll(&mut cx) {
+            std::task::Poll::Ready(Err(e)) => assert!(e.contains("outside the allowed folder"), "{e}"),
+            std::task::Poll::Ready(Ok(_)) => panic!("probed a file outside the root"),
+            std::task::Poll::Pending => panic!("got as far as ffprobe"),
+        }
+    }
+
+    #[test]
+    fn priority_maps_to_nice_levels() {
+        assert_eq!(nice_level(None), Ok(0));
+        assert_eq!(nice_level(Some("low")), Ok(10));
+        assert!(nice_level(Some("urgent")).is_err());
+        // unix can't raise priority without root
+        assert_eq!(nice_level(Some("high")).is_err(), cfg!(unix));
+    }
+
+    #[test]
+    fn resume_state_keeps_extra_metadata() {
+        let options = ConversionOptions {
+            extra_metadata: vec![("R128_TRACK_GAIN".to_string(), "-512".to_string())],
+            ..opts()
+        };
+        let saved = serde_json::to_string(&options).unwrap();
+        let loaded: ConversionOptions = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.extra_metadata, options.extra_metadata);
+    }
+
+    #[test]
+    fn truncated_result_carries_the_warning() {
+        let stats = FfmpegStats::default();
+        let capped = conversion_result(&ConversionOptions { max_duration_secs: Some(4.0), ..opts() }, &stats, &[]);
+        assert!(capped.truncated);
+        assert!(capped.warning.is_some_and(|w| w.contains("4s limit")));
+        let full = conversion_result(&opts(), &stats, &[]);
+        assert!(!full.truncated && full.warning.is_none());
+    }
+
+    #[test]
+    fn clamped_audio_bitrate_is_noted() {
+        let options = ConversionOptions {
+            output_path: "out.mp3".to_string(),
+            format: Some("mp3".to_string()),
+            is_audio_only: true,
+            audio_bitrate_kbps: Some(400),
+            ..opts()
+        };
+        let mut notes = Vec::new();
+        let args = build_ffmpeg_args_noted(&options, &mut notes).unwrap();
+        assert_eq!(value_after(&args, "-b:a"), Some("320k"));
+        assert_eq!(notes, ["Clamped libmp3lame audio bitrate from 400k to 320k"]);
+    }
+}
@@ -519,7 +519,12 @@ function App() {
       await autoOpenAndExitIfEnabled(outputPath);
     } catch (err) {
       console.error(err);
-      setStatus(`Conversion failed: ${err}`);
+      // run_conversion rejects with { code, message, exit_code }
+      const message =
+        typeof err === "object" && err !== null && "message" in err
+          ? String((err as { message: unknown }).message)
+          : String(err);
+      setStatus(`Conversion failed: ${message}`);
       setStep(4);
     } finally {
       setConversionRunning(false);
@@ -5,6 +5,16 @@ export type MediaInfo = {
   fps?: number;
   bitrate_kbps?: number;
   has_video: boolean;
+  has_audio: boolean;
+  is_image: boolean;
+  rotation?: number;
+  color_primaries?: string;
+  color_transfer?: string;
+  color_space?: string;
+  // positive when video runs longer than audio
+  av_duration_mismatch_secs?: number;
+  is_spherical: boolean;
+  sample_rate?: number;
 };
 
 export type NumericPreset = {
@@ -61,8 +71,8 @@ export const AUDIO_FORMAT_OPTIONS: FormatOption[] = [
 
 export const VIDEO_CODECS_BY_FORMAT: Record<string, string[]> = {
   mp4: ["libx264", "libx265"],
-  mov: ["libx264", "libx265", "prores_ks", "mjpeg"],
-  mkv: ["libx264", "libx265", "libvpx-vp9", "prores_ks", "mjpeg"],
+  mov: ["libx264", "libx265", "prores_ks", "dnxhd", "mjpeg"],
+  mkv: ["libx264", "libx265", "libvpx-vp9", "prores_ks", "dnxhd", "mjpeg"],
   webm: ["libvpx-vp9"],
   avi: ["libx264", "mjpeg"],
   flv: ["libx264"],
@@ -70,7 +80,7 @@ export const VIDEO_CODECS_BY_FORMAT: Record<string, string[]> = {
 };
 
 export const AUDIO_CODECS_BY_FORMAT: Record<string, string[]> = {
-  mp4: ["aac", "libmp3lame"],
+  mp4: ["aac", "libmp3lame", "libopus"],
   mov: ["aac"],
   mkv: ["aac", "libopus", "libvorbis", "libmp3lame", "flac"],
   webm: ["libopus", "libvorbis"],
@@ -83,6 +93,11 @@ export const AUDIO_CODECS_BY_FORMAT: Record<string, string[]> = {
   m4a: ["aac"],
   ogg: ["libvorbis"],
   opus: ["libopus"],
+  caf: ["alac", "libopus", "pcm_s16le"],
+  s16le: ["pcm_s16le"],
+  s24le: ["pcm_s24le"],
+  f32le: ["pcm_f32le"],
+  raw: ["pcm_s16le"],
 };
 
 export const SETTINGS_STORE_FILE = "settings.json";
\ No newline at end of file